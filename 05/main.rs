@@ -1,5 +1,7 @@
 use std::fs::File;
+use std::sync::Arc;
 
+use intcode::isa::Profile;
 use intcode::Intcode;
 
 fn run_tests(prog: &mut Intcode, system_id: i64) -> i64 {
@@ -15,6 +17,7 @@ fn run_tests(prog: &mut Intcode, system_id: i64) -> i64 {
 fn main() {
     let file = File::open("05/input.txt").expect("where input bb");
     let prog = &mut Intcode::read(file).expect("cannot read intcode");
+    prog.set_instruction_set(Arc::new(Profile::Day5));
     println!("=== Part 1 ===");
     println!("Diagnostic Code: {}", run_tests(prog, 1));
     println!("=== Part 2 ===");
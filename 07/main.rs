@@ -1,130 +1,241 @@
-use intcode;
-use intcode::{Intcode, Result};
-use permutohedron::control::Control;
-use permutohedron::heap_recursive;
-use std::cmp::max;
-use std::fs::File;
-use std::sync::mpsc;
-use std::thread;
-
-fn test_amp(prog: &mut Intcode, sequences: &[i64]) -> Result<i64> {
-    sequences.iter().copied().try_fold(0, |input, sequence| {
-        let inputs = [sequence, input];
-        let mut inputs_iter = inputs.iter();
-        let mut output = None;
-        prog.run(|| *inputs_iter.next().unwrap(), |out| output = Some(out))?;
-        Ok(output.expect("no output"))
-    })
+use intcode::amplifier::{self, AmpConfig, AmpSearch};
+use intcode::{Error, Intcode};
+use std::fs::{self, File};
+use std::process;
+
+const USAGE: &str = "usage: aoc-07 [<program>] [--amps <n>] [--phases <a>..<b>|<a>,<b>,...] \
+[--repeat] [--initial <signal>] [--feedback] [--csv <file>] [--json <file>]";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("aoc-07: {}", msg);
+    process::exit(1);
 }
 
-fn test_amp_loopback(prog: &mut Intcode, sequences: &[i64]) -> Result<i64> {
-    let (tx_begin, mut rx_next) = mpsc::sync_channel(0);
-    // TODO: implement with a thread pool (or crazier, with async!).
-    let mut threads = Vec::new();
-    for (i, sequence) in sequences.into_iter().enumerate() {
-        let sequence = *sequence;
-        let mut prog = prog.clone();
-        let mut gave_sequence = false;
-        let rx = rx_next;
-        let channel = mpsc::sync_channel(0);
-        let tx = channel.0;
-        rx_next = channel.1;
-        threads.push(
-            thread::Builder::new()
-                .name(format!("Amp {}", i))
-                .spawn(move || {
-                    prog.run(
-                        move || {
-                            if gave_sequence {
-                                rx.recv().expect("recv err")
-                            } else {
-                                gave_sequence = true;
-                                sequence
-                            }
-                        },
-                        move |out| tx.send(out).expect("send err"),
-                    )
-                    .err()
-                })
-                .unwrap(),
-        );
+fn parse_int<T: std::str::FromStr>(s: &str) -> T {
+    s.trim()
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("invalid number {:?}", s)))
+}
+
+/// Parses `a..b`, `a..=b` or a comma-separated list.
+fn parse_phases(s: &str) -> Vec<i64> {
+    if let Some((a, b)) = s.split_once("..=") {
+        (parse_int(a)..=parse_int(b)).collect()
+    } else if let Some((a, b)) = s.split_once("..") {
+        (parse_int(a)..parse_int(b)).collect()
+    } else {
+        s.split(',').map(parse_int).collect()
     }
-    let rx_end = rx_next;
-    tx_begin.send(0).expect("send err at begin");
-    let out = loop {
-        let value = rx_end.recv().expect("recv err on end");
-        // The beginning channel will disconnect when the thread finishes.
-        // This notifies us that the first amp has finished and its recv end has destructed.
-        // Yay for unbuffered SyncChannel!
-        if let Some(err) = tx_begin.send(value).err() {
-            break err.0;
+}
+
+#[derive(Default)]
+struct Options {
+    program: Option<String>,
+    amps: Option<usize>,
+    phases: Option<Vec<i64>>,
+    repeat: bool,
+    initial_signal: Option<i64>,
+    feedback: bool,
+    csv: Option<String>,
+    json: Option<String>,
+}
+
+impl Options {
+    fn is_custom(&self) -> bool {
+        self.amps.is_some()
+            || self.phases.is_some()
+            || self.repeat
+            || self.initial_signal.is_some()
+            || self.feedback
+            || self.csv.is_some()
+            || self.json.is_some()
+    }
+
+    /// The circuit to search, defaulting to the one from part 1, or part 2 with
+    /// `--feedback`.
+    fn config(&self) -> AmpConfig {
+        let mut config = if self.feedback {
+            AmpConfig::feedback_loop()
+        } else {
+            AmpConfig::series()
+        };
+        if let Some(phases) = &self.phases {
+            config.phases = phases.clone();
+            config.amps = phases.len();
         }
-    };
-    for t in threads {
-        if let Some(err) = t.join().unwrap() {
-            return Err(err);
+        if let Some(amps) = self.amps {
+            config.amps = amps;
+        }
+        if let Some(signal) = self.initial_signal {
+            config.initial_signal = signal;
         }
+        config.repeat = self.repeat;
+        config
     }
-    Ok(out)
 }
 
-fn test_all_amps<F: Fn(&mut Intcode, &[i64]) -> Result<i64>>(
-    prog: &mut Intcode,
-    sequences: &[i64],
-    func: F,
-) -> Result<i64> {
-    let mut biggest = std::i64::MIN;
-    let err = heap_recursive(&mut Vec::from(sequences), |permutation| {
-        match func(prog, permutation) {
-            Ok(x) => {
-                biggest = max(biggest, x);
-                Control::Continue
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Options {
+    let mut opts = Options::default();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| fail(&format!("{} needs a value", arg)))
+        };
+        match arg.as_str() {
+            "--amps" => opts.amps = Some(parse_int(&value())),
+            "--phases" => opts.phases = Some(parse_phases(&value())),
+            "--repeat" => opts.repeat = true,
+            "--initial" => opts.initial_signal = Some(parse_int(&value())),
+            "--feedback" => opts.feedback = true,
+            "--csv" => opts.csv = Some(value()),
+            "--json" => opts.json = Some(value()),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
             }
-            Err(err) => Control::Break(err),
+            _ if arg.starts_with('-') => fail(&format!("unknown option {}\n{}", arg, USAGE)),
+            _ if opts.program.is_none() => opts.program = Some(arg),
+            _ => fail(USAGE),
         }
-    })
-    .break_value();
-    match err {
-        Some(err) => Err(err),
-        None => Ok(biggest),
     }
+    opts
 }
 
-fn main() {
-    let file = File::open("07/input.txt").expect("where input bb");
-    let prog = &mut Intcode::read(file).expect("cannot read intcode");
-    let biggest_amp = match test_all_amps(prog, &[0, 1, 2, 3, 4], test_amp) {
-        Ok(x) => x,
-        Err(e) => panic!("intcode error, {:?}, memory:\n{:?}", e, prog.memory()),
-    };
-    println!("Part 1: {}", biggest_amp);
-    let biggest_amp_loopback = match test_all_amps(prog, &[5, 6, 7, 8, 9], test_amp_loopback) {
-        Ok(x) => x,
-        Err(e) => panic!("intcode error, {:?}, memory:\n{:?}", e, prog.memory()),
+/// Reports which amplifier failed and the state it failed in.
+fn crash(error: Error) -> ! {
+    match error {
+        Error::Machine(crash) => panic!("intcode error in {}", crash),
+        e => panic!("intcode error, {:?}", e),
+    }
+}
+
+fn report(part: u32, search: &AmpSearch) {
+    println!(
+        "Part {}: {} (phases {:?}, signals {:?})",
+        part,
+        search.best.thruster(),
+        search.best.phases,
+        search.best.signals
+    );
+}
+
+fn run_custom(prog: &Intcode, opts: &Options) {
+    let config = opts.config();
+    if config.amps == 0 {
+        fail("need at least one amplifier");
+    }
+    match config.sequences().count() {
+        Some(0) => fail(&format!(
+            "{} phases are not enough for {} amplifiers without --repeat",
+            config.phases.len(),
+            config.amps
+        )),
+        None => fail("too many sequences"),
+        Some(_) => {}
+    }
+    let keep_table = opts.csv.is_some() || opts.json.is_some();
+    let search = config.search(prog, keep_table).unwrap_or_else(|e| crash(e));
+    println!(
+        "Best: {} (phases {:?}, signals {:?})",
+        search.best.thruster(),
+        search.best.phases,
+        search.best.signals
+    );
+    let write = |path: &String, contents: String| {
+        fs::write(path, contents).unwrap_or_else(|e| fail(&format!("cannot write {}: {}", path, e)))
     };
-    println!("Part 2: {}", biggest_amp_loopback);
+    if let Some(path) = &opts.csv {
+        write(path, search.table_csv());
+    }
+    if let Some(path) = &opts.json {
+        write(path, search.to_json());
+    }
+}
+
+fn main() {
+    let opts = parse_args(std::env::args().skip(1));
+    let path = opts.program.as_deref().unwrap_or("07/input.txt");
+    let file = File::open(path).expect("where input bb");
+    let prog = &Intcode::read(file).expect("cannot read intcode");
+    if opts.is_custom() {
+        run_custom(prog, &opts);
+        return;
+    }
+    let amps = amplifier::search(prog, &[0, 1, 2, 3, 4], false, false).unwrap_or_else(|e| crash(e));
+    report(1, &amps);
+    let amps_loopback =
+        amplifier::search(prog, &[5, 6, 7, 8, 9], true, false).unwrap_or_else(|e| crash(e));
+    report(2, &amps_loopback);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn test_max_with(prog_data: Vec<i64>, max_signal: i64, sequence: [i64; 5], feedback: bool) {
+        let prog = &Intcode::new(prog_data);
+        let run = amplifier::run_amplifiers(prog, &sequence, feedback).unwrap();
+        assert_eq!(run.thruster(), max_signal);
+        let search = amplifier::search(prog, &sequence, feedback, true).unwrap();
+        assert_eq!(search.best, run);
+        assert_eq!(search.table.unwrap().len(), 120);
+    }
+
     fn test_max(prog_data: Vec<i64>, max_signal: i64, sequence: [i64; 5]) {
-        let prog = &mut Intcode::new(prog_data);
-        assert_eq!(test_amp(prog, &sequence).unwrap(), max_signal);
+        test_max_with(prog_data, max_signal, sequence, false);
+    }
+
+    fn test_max_loopback(prog_data: Vec<i64>, max_signal: i64, sequence: [i64; 5]) {
+        test_max_with(prog_data, max_signal, sequence, true);
+    }
+
+    #[test]
+    fn test_export() {
+        let prog = &Intcode::new(vec![
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ]);
+        let search = amplifier::search(prog, &[4, 3, 2, 1, 0], false, false).unwrap();
+        assert_eq!(search.best.signals, vec![4, 43, 432, 4321, 43210]);
         assert_eq!(
-            test_all_amps(prog, &sequence, test_amp).unwrap(),
-            max_signal
+            search.to_json(),
+            "{\"best\":{\"phases\":[4,3,2,1,0],\"signals\":[4,43,432,4321,43210],\"thruster\":43210}}"
         );
+        let search = amplifier::search(prog, &[1, 0], false, true).unwrap();
+        assert_eq!(search.table_csv(), "amp0,amp1,thruster\n0,1,1\n1,0,10\n");
     }
 
-    fn test_max_loopback(prog_data: Vec<i64>, max_signal: i64, sequence: [i64; 5]) {
-        let prog = &mut Intcode::new(prog_data);
-        assert_eq!(test_amp_loopback(prog, &sequence).unwrap(), max_signal);
+    #[test]
+    fn test_config() {
+        let prog = &Intcode::new(vec![
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ]);
+        let config = AmpConfig {
+            amps: 3,
+            phases: vec![1, 2],
+            repeat: true,
+            initial_signal: 5,
+            feedback: false,
+        };
+        let search = config.search(prog, true).unwrap();
+        assert_eq!(search.table.unwrap().len(), 8);
+        assert_eq!(search.best.phases, vec![2, 2, 2]);
+        assert_eq!(search.best.signals, vec![52, 522, 5222]);
+
+        let opts = parse_args(
+            ["--phases", "3..=6", "--amps", "2", "--initial", "-1"]
+                .iter()
+                .map(|s| s.to_string()),
+        );
         assert_eq!(
-            test_all_amps(prog, &sequence, test_amp_loopback).unwrap(),
-            max_signal
+            opts.config(),
+            AmpConfig {
+                amps: 2,
+                phases: vec![3, 4, 5, 6],
+                initial_signal: -1,
+                ..AmpConfig::series()
+            }
         );
+        assert_eq!(opts.config().sequences().count(), Some(12));
     }
 
     #[test]
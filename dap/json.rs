@@ -0,0 +1,295 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Just enough JSON for the debug adapter protocol.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Members in the order they were written.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(members: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// The member `key` of an object, or null.
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(k, _)| k == key)
+                .map_or(&Json::Null, |(_, v)| v),
+            _ => &Json::Null,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Json::Int(x) => Some(x),
+            Json::Float(x) if x.fract() == 0.0 => Some(x as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut chars = text.chars().peekable();
+        let value = parse_value(&mut chars)?;
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            None => Ok(value),
+            Some(c) => Err(format!("unexpected {:?} after value", c)),
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(x: i64) -> Json {
+        Json::Int(x)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(x: usize) -> Json {
+        Json::Int(x as i64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(items: Vec<Json>) -> Json {
+        Json::Array(items)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Int(x) => write!(f, "{}", x),
+            Json::Float(x) if x.is_finite() => write!(f, "{}", x),
+            Json::Float(_) => f.write_str("null"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(members) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+type Input<'a> = Peekable<Chars<'a>>;
+
+fn skip_whitespace(chars: &mut Input) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn expect_word(chars: &mut Input, word: &str, value: Json) -> Result<Json, String> {
+    for expected in word.chars() {
+        if chars.next() != Some(expected) {
+            return Err(format!("expected {}", word));
+        }
+    }
+    Ok(value)
+}
+
+fn parse_value(chars: &mut Input) -> Result<Json, String> {
+    skip_whitespace(chars);
+    match chars.peek().copied() {
+        Some('n') => expect_word(chars, "null", Json::Null),
+        Some('t') => expect_word(chars, "true", Json::Bool(true)),
+        Some('f') => expect_word(chars, "false", Json::Bool(false)),
+        Some('"') => parse_string(chars).map(Json::String),
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&']') {
+                chars.next();
+                return Ok(Json::Array(items));
+            }
+            loop {
+                items.push(parse_value(chars)?);
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => return Ok(Json::Array(items)),
+                    _ => return Err("expected , or ] in array".to_string()),
+                }
+            }
+        }
+        Some('{') => {
+            chars.next();
+            let mut members = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                return Ok(Json::Object(members));
+            }
+            loop {
+                skip_whitespace(chars);
+                let key = parse_string(chars)?;
+                skip_whitespace(chars);
+                if chars.next() != Some(':') {
+                    return Err("expected : in object".to_string());
+                }
+                members.push((key, parse_value(chars)?));
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some('}') => return Ok(Json::Object(members)),
+                    _ => return Err("expected , or } in object".to_string()),
+                }
+            }
+        }
+        Some(c) if c == '-' || c.is_ascii_digit() => {
+            let mut text = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_digit() || "+-.eE".contains(c)) {
+                    break;
+                }
+                text.push(c);
+                chars.next();
+            }
+            text.parse()
+                .map(Json::Int)
+                .or_else(|_| text.parse().map(Json::Float))
+                .map_err(|_| format!("bad number {:?}", text))
+        }
+        Some(c) => Err(format!("unexpected {:?}", c)),
+        None => Err("unexpected end of input".to_string()),
+    }
+}
+
+fn parse_string(chars: &mut Input) -> Result<String, String> {
+    if chars.next() != Some('"') {
+        return Err("expected string".to_string());
+    }
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => match chars.next() {
+                Some('n') => s.push('\n'),
+                Some('r') => s.push('\r'),
+                Some('t') => s.push('\t'),
+                Some('b') => s.push('\u{8}'),
+                Some('f') => s.push('\u{c}'),
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let code = u32::from_str_radix(&hex, 16)
+                        .map_err(|_| format!("bad escape \\u{}", hex))?;
+                    // Surrogate pairs aren't joined; nothing we read needs them.
+                    s.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+                }
+                Some(c) => s.push(c),
+                None => return Err("unterminated string".to_string()),
+            },
+            Some(c) => s.push(c),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = r#"{"seq":1,"args":{"lines":[1,-2,3.5],"name":"a \"b\"\n","ok":true,"x":null}}"#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(json.get("seq").as_i64(), Some(1));
+        assert_eq!(json.get("args").get("lines").as_array()[1], Json::Int(-2));
+        assert_eq!(json.get("args").get("name").as_str(), Some("a \"b\"\n"));
+        assert_eq!(json.to_string(), text);
+        assert!(Json::parse("[1,").is_err());
+        assert!(Json::parse("{} x").is_err());
+    }
+}
@@ -0,0 +1,13 @@
+mod json;
+mod server;
+
+use std::io;
+
+fn main() {
+    let stdin = io::stdin();
+    let mut server = server::Server::new(io::stdout());
+    if let Err(e) = server.serve(stdin.lock()) {
+        eprintln!("intcode-dap: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,760 @@
+use crate::json::Json;
+use intcode::isa::Profile;
+use intcode::{Error, Intcode, StepResult};
+use std::collections::{BTreeSet, VecDeque};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+
+/// Intcode machines have a single thread.
+const THREAD: i64 = 1;
+const REGISTERS: i64 = 1;
+const INPUT: i64 = 2;
+const OUTPUT: i64 = 3;
+const MEMORY: i64 = 4;
+/// Memory is shown in chunks of `CHUNK` cells, with variable references
+/// counting up from `CHUNKS`.
+const CHUNK: usize = 64;
+const CHUNKS: i64 = 1000;
+/// Continuing gives up after this many instructions, so a program stuck in a
+/// loop doesn't hang the editor.
+const MAX_STEPS: usize = 10_000_000;
+
+/// Reads a message framed with a `Content-Length` header, or `None` at the end
+/// of the input.
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Json>> {
+    let bad = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            let value = value.trim();
+            length = Some(
+                value
+                    .parse()
+                    .map_err(|_| bad(format!("bad Content-Length {:?}", value)))?,
+            );
+        }
+    }
+    let length = length.ok_or_else(|| bad("missing Content-Length".to_string()))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|e| bad(e.to_string()))?;
+    Json::parse(&body).map(Some).map_err(bad)
+}
+
+pub fn write_message<W: Write>(out: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    out.flush()
+}
+
+/// The address of the first value on each line of a program's source.
+pub struct SourceMap {
+    lines: Vec<Option<usize>>,
+}
+
+impl SourceMap {
+    pub fn new(text: &str) -> Self {
+        let mut address = 0;
+        let mut lines = Vec::new();
+        for line in text.lines() {
+            let code = line.split('#').next().unwrap_or("");
+            let values = code.split(',').filter(|v| !v.trim().is_empty()).count();
+            lines.push(if values > 0 { Some(address) } else { None });
+            address += values;
+        }
+        SourceMap { lines }
+    }
+
+    /// The address of the first value on a 1-based line.
+    pub fn address(&self, line: usize) -> Option<usize> {
+        *self.lines.get(line.checked_sub(1)?)?
+    }
+
+    /// The 1-based line holding the value at `address`.
+    pub fn line(&self, address: usize) -> Option<usize> {
+        let mut found = None;
+        for (i, start) in self.lines.iter().enumerate() {
+            match start {
+                Some(start) if *start > address => break,
+                Some(_) => found = Some(i + 1),
+                None => {}
+            }
+        }
+        found
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Stop {
+    Entry,
+    Step,
+    Breakpoint,
+    /// The program is at an input instruction with nothing queued.
+    WaitingForInput,
+    StepLimit,
+}
+
+/// A debug adapter for one Intcode program, speaking the debug adapter
+/// protocol over a pair of streams.
+///
+/// Requests are handled one at a time, so a continued program runs to its next
+/// stop before the next request is read. Inputs are queued from the debug
+/// console with `input 1,2,3`.
+pub struct Server<W: Write> {
+    out: W,
+    seq: i64,
+    /// Events to send after the response to the current request.
+    events: Vec<(String, Json)>,
+    machine: Option<Intcode>,
+    path: String,
+    source: SourceMap,
+    stop_on_entry: bool,
+    line_breakpoints: BTreeSet<usize>,
+    instruction_breakpoints: BTreeSet<usize>,
+    inputs: VecDeque<i64>,
+    outputs: Vec<i64>,
+    /// Why the machine can't run any more: it halted or failed.
+    finished: Option<String>,
+    /// Whether the client has been told the program stopped, so resuming
+    /// doesn't stop again at a breakpoint on the current pc.
+    stopped: bool,
+    disconnected: bool,
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(out: W) -> Self {
+        Server {
+            out,
+            seq: 0,
+            events: Vec::new(),
+            machine: None,
+            path: String::new(),
+            source: SourceMap { lines: Vec::new() },
+            stop_on_entry: false,
+            line_breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            inputs: VecDeque::new(),
+            outputs: Vec::new(),
+            finished: None,
+            stopped: false,
+            disconnected: false,
+        }
+    }
+
+    /// Handles requests until the client disconnects or closes the input.
+    pub fn serve<R: BufRead>(&mut self, mut input: R) -> io::Result<()> {
+        while let Some(message) = read_message(&mut input)? {
+            if message.get("type").as_str() == Some("request") {
+                self.handle(&message)?;
+            }
+            if self.disconnected {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, mut members: Vec<(&str, Json)>) -> io::Result<()> {
+        self.seq += 1;
+        members.insert(0, ("seq", self.seq.into()));
+        write_message(&mut self.out, &Json::object(members))
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.events.push((event.to_string(), body));
+    }
+
+    fn console(&mut self, category: &str, text: String) {
+        let body = Json::object(vec![("category", category.into()), ("output", text.into())]);
+        self.event("output", body);
+    }
+
+    fn handle(&mut self, request: &Json) -> io::Result<()> {
+        let command = request.get("command").as_str().unwrap_or("");
+        let result = self.dispatch(command, request.get("arguments"));
+        let mut response = vec![
+            ("type", "response".into()),
+            ("request_seq", request.get("seq").clone()),
+            ("success", result.is_ok().into()),
+            ("command", command.into()),
+        ];
+        match result {
+            Ok(body) => response.push(("body", body)),
+            Err(message) => response.push(("message", message.into())),
+        }
+        self.send(response)?;
+        for (event, body) in std::mem::take(&mut self.events) {
+            self.send(vec![
+                ("type", "event".into()),
+                ("event", event.into()),
+                ("body", body),
+            ])?;
+        }
+        Ok(())
+    }
+
+    fn machine(&self) -> Result<&Intcode, String> {
+        self.machine
+            .as_ref()
+            .ok_or_else(|| "no program launched".to_string())
+    }
+
+    fn dispatch(&mut self, command: &str, args: &Json) -> Result<Json, String> {
+        match command {
+            "initialize" => Ok(Json::object(vec![
+                ("supportsConfigurationDoneRequest", true.into()),
+                ("supportsInstructionBreakpoints", true.into()),
+                ("supportsTerminateRequest", true.into()),
+            ])),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(Json::object(vec![])),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    self.stopped(Stop::Entry);
+                } else {
+                    self.execute(MAX_STEPS)?;
+                }
+                Ok(Json::Null)
+            }
+            "threads" => {
+                let thread = Json::object(vec![("id", THREAD.into()), ("name", "intcode".into())]);
+                Ok(Json::object(vec![("threads", vec![thread].into())]))
+            }
+            "stackTrace" => self.stack_trace(),
+            "scopes" => {
+                let scope = |name: &str, reference: i64| {
+                    Json::object(vec![
+                        ("name", name.into()),
+                        ("variablesReference", reference.into()),
+                        ("expensive", false.into()),
+                    ])
+                };
+                let scopes = vec![
+                    scope("Registers", REGISTERS),
+                    scope("Input", INPUT),
+                    scope("Output", OUTPUT),
+                    scope("Memory", MEMORY),
+                ];
+                Ok(Json::object(vec![("scopes", scopes.into())]))
+            }
+            "variables" => self.variables(args.get("variablesReference").as_i64().unwrap_or(0)),
+            "evaluate" => self.evaluate(args.get("expression").as_str().unwrap_or("")),
+            "continue" => {
+                self.execute(MAX_STEPS)?;
+                Ok(Json::object(vec![("allThreadsContinued", true.into())]))
+            }
+            "next" | "stepIn" | "stepOut" | "stepInstruction" => {
+                self.execute(1)?;
+                Ok(Json::Null)
+            }
+            // Programs only run while a request is being handled, so there's
+            // never anything to pause.
+            "pause" => Err("pause is not supported".to_string()),
+            "disconnect" | "terminate" => {
+                self.disconnected = true;
+                Ok(Json::Null)
+            }
+            _ => Err(format!("unsupported command {:?}", command)),
+        }
+    }
+
+    fn launch(&mut self, args: &Json) -> Result<Json, String> {
+        let path = args
+            .get("program")
+            .as_str()
+            .ok_or("launch needs a program")?;
+        let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        let mut machine = Intcode::parse(&text).map_err(|e| format!("{}: {:?}", path, e))?;
+        if let Some(profile) = args.get("profile").as_str() {
+            machine.set_instruction_set(Arc::new(profile.parse::<Profile>()?));
+        }
+        self.inputs.clear();
+        self.outputs.clear();
+        self.finished = None;
+        self.stopped = false;
+        match args.get("input") {
+            Json::Null => {}
+            Json::String(values) => {
+                self.queue_inputs(values)?;
+            }
+            Json::Array(values) => {
+                for value in values {
+                    let value = value.as_i64().ok_or("input values must be integers")?;
+                    self.inputs.push_back(value);
+                }
+            }
+            _ => return Err("input must be a list or a string of values".to_string()),
+        }
+        self.machine = Some(machine);
+        self.path = path.to_string();
+        self.source = SourceMap::new(&text);
+        self.stop_on_entry = args.get("stopOnEntry").as_bool().unwrap_or(false);
+        self.event("initialized", Json::Null);
+        Ok(Json::Null)
+    }
+
+    fn queue_inputs(&mut self, values: &str) -> Result<usize, String> {
+        let values = values
+            .split(',')
+            .map(|v| v.trim().parse())
+            .collect::<Result<Vec<i64>, _>>()
+            .map_err(|_| format!("bad input values {:?}", values))?;
+        self.inputs.extend(values.iter());
+        Ok(values.len())
+    }
+
+    fn set_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        self.line_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in args.get("breakpoints").as_array() {
+            let line = breakpoint.get("line").as_i64().unwrap_or(0);
+            let address = self.source.address(line as usize);
+            let mut result = vec![
+                ("verified", address.is_some().into()),
+                ("line", line.into()),
+            ];
+            match address {
+                Some(address) => {
+                    self.line_breakpoints.insert(address);
+                    result.push(("instructionReference", address.to_string().into()));
+                }
+                None => result.push(("message", "no program values on this line".into())),
+            }
+            breakpoints.push(Json::object(result));
+        }
+        Ok(Json::object(vec![("breakpoints", breakpoints.into())]))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        self.instruction_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in args.get("breakpoints").as_array() {
+            let reference = breakpoint
+                .get("instructionReference")
+                .as_str()
+                .unwrap_or("");
+            let offset = breakpoint.get("offset").as_i64().unwrap_or(0);
+            let address = reference
+                .parse::<i64>()
+                .ok()
+                .map(|address| address + offset)
+                .filter(|&address| address >= 0);
+            if let Some(address) = address {
+                self.instruction_breakpoints.insert(address as usize);
+            }
+            breakpoints.push(Json::object(vec![
+                ("verified", address.is_some().into()),
+                ("instructionReference", reference.into()),
+            ]));
+        }
+        Ok(Json::object(vec![("breakpoints", breakpoints.into())]))
+    }
+
+    fn stopped(&mut self, stop: Stop) {
+        let (reason, description) = match stop {
+            Stop::Entry => ("entry", None),
+            Stop::Step => ("step", None),
+            Stop::Breakpoint => ("breakpoint", None),
+            Stop::WaitingForInput => ("pause", Some("waiting for input")),
+            Stop::StepLimit => ("pause", Some("step limit reached")),
+        };
+        let mut body = vec![
+            ("reason", reason.into()),
+            ("threadId", THREAD.into()),
+            ("allThreadsStopped", true.into()),
+        ];
+        if let Some(description) = description {
+            body.push(("description", description.into()));
+        }
+        self.stopped = true;
+        self.event("stopped", Json::object(body));
+        if stop == Stop::WaitingForInput {
+            let hint = "waiting for input; queue some with `input <values>`\n".to_string();
+            self.console("console", hint);
+        }
+    }
+
+    /// Runs up to `max_steps` instructions, stopping early at breakpoints
+    /// other than one it's already stopped at.
+    fn execute(&mut self, max_steps: usize) -> Result<(), String> {
+        if let Some(reason) = &self.finished {
+            return Err(reason.clone());
+        }
+        let Server {
+            machine,
+            line_breakpoints,
+            instruction_breakpoints,
+            inputs,
+            outputs,
+            stopped,
+            ..
+        } = self;
+        let machine = machine.as_mut().ok_or("no program launched")?;
+        let start = outputs.len();
+        let mut stop = Stop::StepLimit;
+        let mut result = Ok(());
+        for steps in 0..max_steps {
+            let pc = machine.pc();
+            if (steps > 0 || !*stopped)
+                && (line_breakpoints.contains(&pc) || instruction_breakpoints.contains(&pc))
+            {
+                stop = Stop::Breakpoint;
+                break;
+            }
+            match machine.try_step(&mut || inputs.pop_front(), &mut |out| outputs.push(out)) {
+                Ok(StepResult::Continue) | Ok(StepResult::Paused(_)) => stop = Stop::Step,
+                Ok(StepResult::WaitingForInput) => {
+                    stop = Stop::WaitingForInput;
+                    break;
+                }
+                Ok(StepResult::Complete) => {
+                    result = Err(None);
+                    break;
+                }
+                Err(e) => {
+                    result = Err(Some((pc, e)));
+                    break;
+                }
+            }
+        }
+        if max_steps > 1 && stop == Stop::Step {
+            stop = Stop::StepLimit;
+        }
+        let printed: Vec<String> = self.outputs[start..]
+            .iter()
+            .map(|out| format!("{}\n", out))
+            .collect();
+        for text in printed {
+            self.console("stdout", text);
+        }
+        match result {
+            Ok(()) => self.stopped(stop),
+            Err(None) => {
+                self.finished = Some("the program halted".to_string());
+                self.console("console", "program halted\n".to_string());
+                self.event("exited", Json::object(vec![("exitCode", 0i64.into())]));
+                self.event("terminated", Json::Null);
+            }
+            Err(Some((pc, error))) => self.failed(pc, error),
+        }
+        Ok(())
+    }
+
+    fn failed(&mut self, pc: usize, error: Error) {
+        let text = format!("failed at pc {}: {:?}", pc, error);
+        self.console("stderr", format!("{}\n", text));
+        self.event(
+            "stopped",
+            Json::object(vec![
+                ("reason", "exception".into()),
+                ("threadId", THREAD.into()),
+                ("allThreadsStopped", true.into()),
+                ("text", text.clone().into()),
+            ]),
+        );
+        self.finished = Some(text);
+    }
+
+    fn stack_trace(&self) -> Result<Json, String> {
+        let machine = self.machine()?;
+        let pc = machine.pc();
+        let opcode = machine.memory().get(pc).unwrap_or(0);
+        let name = machine
+            .instruction_set()
+            .instruction(opcode % 100)
+            .map_or("???", |instruction| instruction.name());
+        let mut frame = vec![
+            ("id", 1i64.into()),
+            ("name", format!("{}: {}", pc, name).into()),
+            ("line", self.source.line(pc).unwrap_or(0).into()),
+            ("column", 1i64.into()),
+            ("instructionPointerReference", pc.to_string().into()),
+        ];
+        if !self.path.is_empty() {
+            frame.push((
+                "source",
+                Json::object(vec![("path", self.path.as_str().into())]),
+            ));
+        }
+        Ok(Json::object(vec![
+            ("stackFrames", vec![Json::object(frame)].into()),
+            ("totalFrames", 1i64.into()),
+        ]))
+    }
+
+    fn variables(&self, reference: i64) -> Result<Json, String> {
+        let machine = self.machine()?;
+        let variable = |name: String, value: String, reference: i64| {
+            Json::object(vec![
+                ("name", name.into()),
+                ("value", value.into()),
+                ("variablesReference", reference.into()),
+            ])
+        };
+        let list = |values: &mut dyn Iterator<Item = &i64>| {
+            values
+                .enumerate()
+                .map(|(i, v)| variable(i.to_string(), v.to_string(), 0))
+                .collect()
+        };
+        let variables: Vec<Json> = match reference {
+            REGISTERS => {
+                let status = self.finished.as_deref().unwrap_or("stopped");
+                vec![
+                    variable("pc".into(), machine.pc().to_string(), 0),
+                    variable(
+                        "relative base".into(),
+                        machine.relative_base().to_string(),
+                        0,
+                    ),
+                    variable("status".into(), status.into(), 0),
+                ]
+            }
+            INPUT => list(&mut self.inputs.iter()),
+            OUTPUT => list(&mut self.outputs.iter()),
+            MEMORY => {
+                let len = machine.memory().len();
+                (0..len)
+                    .step_by(CHUNK)
+                    .map(|start| {
+                        let end = (start + CHUNK).min(len);
+                        let reference = CHUNKS + (start / CHUNK) as i64;
+                        variable(format!("[{}..{}]", start, end), String::new(), reference)
+                    })
+                    .collect()
+            }
+            _ if reference >= CHUNKS => {
+                let start = (reference - CHUNKS) as usize * CHUNK;
+                let memory = machine.memory();
+                (start..(start + CHUNK).min(memory.len()))
+                    .map(|i| variable(format!("[{}]", i), memory[i].to_string(), 0))
+                    .collect()
+            }
+            _ => return Err(format!("no variables for reference {}", reference)),
+        };
+        Ok(Json::object(vec![("variables", variables.into())]))
+    }
+
+    /// Evaluates `pc`, `rb`, an address or `[address]`, or queues inputs with
+    /// `input <values>`.
+    fn evaluate(&mut self, expression: &str) -> Result<Json, String> {
+        let expression = expression.trim();
+        let result = if let Some(values) = expression.strip_prefix("input ") {
+            let queued = self.queue_inputs(values)?;
+            format!("{} queued, {} pending", queued, self.inputs.len())
+        } else {
+            let machine = self.machine()?;
+            match expression {
+                "pc" => machine.pc().to_string(),
+                "rb" => machine.relative_base().to_string(),
+                _ => {
+                    let address = expression.trim_start_matches('[').trim_end_matches(']');
+                    let address: usize = address
+                        .parse()
+                        .map_err(|_| format!("cannot evaluate {:?}", expression))?;
+                    machine.memory().get(address).unwrap_or(0).to_string()
+                }
+            }
+        };
+        Ok(Json::object(vec![
+            ("result", result.into()),
+            ("variablesReference", 0i64.into()),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn request(seq: i64, command: &str, arguments: Json) -> Vec<u8> {
+        let mut out = Vec::new();
+        let message = Json::object(vec![
+            ("seq", seq.into()),
+            ("type", "request".into()),
+            ("command", command.into()),
+            ("arguments", arguments),
+        ]);
+        write_message(&mut out, &message).unwrap();
+        out
+    }
+
+    /// Runs a session, returning everything the server sent.
+    fn serve(script: &[Vec<u8>]) -> Vec<Json> {
+        let mut server = Server::new(Vec::new());
+        server.serve(&script.concat()[..]).unwrap();
+        let mut replies = &server.out[..];
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut replies).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    /// Each event, with its reason or output.
+    fn summary(messages: &[Json]) -> Vec<String> {
+        messages
+            .iter()
+            .filter_map(|m| match m.get("type").as_str()? {
+                "event" => {
+                    let body = m.get("body");
+                    let detail = body.get("reason").as_str().or(body.get("output").as_str());
+                    Some(format!(
+                        "{} {}",
+                        m.get("event").as_str()?,
+                        detail.unwrap_or("")
+                    ))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn source_map() {
+        let map = SourceMap::new("# header\n3,9,\n1001,9,5,9, # add\n\n4,9,99,0\n");
+        assert_eq!(map.address(1), None);
+        assert_eq!(map.address(2), Some(0));
+        assert_eq!(map.address(3), Some(2));
+        assert_eq!(map.address(5), Some(6));
+        assert_eq!(map.line(4), Some(3));
+        assert_eq!(map.line(9), Some(5));
+    }
+
+    #[test]
+    fn scripted_session() {
+        let path = env::temp_dir().join(format!("intcode-dap-{}.txt", std::process::id()));
+        fs::write(&path, "3,9,\n1001,9,5,9,\n4,9,\n99,0\n").unwrap();
+        let path = path.to_str().unwrap();
+        let variables =
+            |reference: i64| Json::object(vec![("variablesReference", reference.into())]);
+        let script = vec![
+            request(1, "initialize", Json::object(vec![])),
+            request(
+                2,
+                "launch",
+                Json::object(vec![("program", path.into()), ("stopOnEntry", true.into())]),
+            ),
+            request(
+                3,
+                "setBreakpoints",
+                Json::object(vec![(
+                    "breakpoints",
+                    vec![Json::object(vec![("line", 3i64.into())])].into(),
+                )]),
+            ),
+            request(4, "configurationDone", Json::Null),
+            request(5, "continue", Json::Null),
+            request(
+                6,
+                "evaluate",
+                Json::object(vec![("expression", "input 7".into())]),
+            ),
+            request(7, "continue", Json::Null),
+            request(8, "stackTrace", Json::Null),
+            request(9, "variables", variables(CHUNKS)),
+            request(10, "stepInstruction", Json::Null),
+            request(11, "variables", variables(OUTPUT)),
+            request(12, "continue", Json::Null),
+            request(13, "disconnect", Json::Null),
+        ];
+        let messages = serve(&script);
+        fs::remove_file(path).unwrap();
+        assert!(messages
+            .iter()
+            .all(|m| m.get("success") != &Json::Bool(false)));
+        assert_eq!(
+            summary(&messages),
+            vec![
+                "initialized ",
+                "stopped entry",
+                "stopped pause",
+                "output waiting for input; queue some with `input <values>`\n",
+                "stopped breakpoint",
+                "output 12\n",
+                "stopped step",
+                "output program halted\n",
+                "exited ",
+                "terminated ",
+            ]
+        );
+        let response = |seq: i64| {
+            let response = messages
+                .iter()
+                .find(|m| m.get("request_seq").as_i64() == Some(seq));
+            response.unwrap().get("body")
+        };
+        let frame = &response(8).get("stackFrames").as_array()[0];
+        assert_eq!(frame.get("name").as_str(), Some("6: out"));
+        assert_eq!(frame.get("line").as_i64(), Some(3));
+        let cells = response(9).get("variables").as_array();
+        assert_eq!(cells[9].get("value").as_str(), Some("12"));
+        let outputs = response(11).get("variables").as_array();
+        assert_eq!(outputs[0].get("value").as_str(), Some("12"));
+    }
+
+    #[test]
+    fn entry_breakpoint_and_relaunch() {
+        let path = env::temp_dir().join(format!("intcode-dap-relaunch-{}.txt", std::process::id()));
+        fs::write(&path, "3,5,4,5,99,0\n").unwrap();
+        let path = path.to_str().unwrap();
+        let launch = |seq: i64, input: Json| {
+            request(
+                seq,
+                "launch",
+                Json::object(vec![("program", path.into()), ("input", input)]),
+            )
+        };
+        let at_entry = Json::object(vec![(
+            "breakpoints",
+            vec![Json::object(vec![("instructionReference", "0".into())])].into(),
+        )]);
+        let script = vec![
+            launch(1, "1,2".into()),
+            request(2, "setInstructionBreakpoints", at_entry.clone()),
+            request(3, "configurationDone", Json::Null),
+            request(4, "continue", Json::Null),
+            request(5, "pause", Json::Null),
+            // The 2 left over from the first launch isn't used.
+            launch(6, Json::Null),
+            request(7, "setInstructionBreakpoints", at_entry),
+            request(8, "configurationDone", Json::Null),
+            request(9, "continue", Json::Null),
+        ];
+        let messages = serve(&script);
+        fs::remove_file(path).unwrap();
+        let failed: Vec<i64> = messages
+            .iter()
+            .filter(|m| m.get("success") == &Json::Bool(false))
+            .filter_map(|m| m.get("request_seq").as_i64())
+            .collect();
+        assert_eq!(failed, vec![5]);
+        assert_eq!(
+            summary(&messages),
+            vec![
+                "initialized ",
+                "stopped breakpoint",
+                "output 1\n",
+                "output program halted\n",
+                "exited ",
+                "terminated ",
+                "initialized ",
+                "stopped breakpoint",
+                "stopped pause",
+                "output waiting for input; queue some with `input <values>`\n",
+            ]
+        );
+    }
+}
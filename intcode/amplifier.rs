@@ -0,0 +1,256 @@
+use crate::network::{Network, NodeId};
+use crate::search::{self, Sequences};
+use crate::{Error, Intcode, Result};
+use std::cmp::Reverse;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// One phase sequence run through a chain of amplifiers.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AmpRun {
+    pub phases: Vec<i64>,
+    /// The last signal each amplifier output, in order.
+    pub signals: Vec<i64>,
+}
+
+impl AmpRun {
+    /// The signal sent to the thrusters, from the last amplifier.
+    pub fn thruster(&self) -> i64 {
+        *self.signals.last().expect("no amplifiers")
+    }
+}
+
+#[derive(Debug)]
+pub struct AmpSearch {
+    /// The phase sequence with the largest thruster signal. Ties go to the
+    /// lexicographically smallest sequence.
+    pub best: AmpRun,
+    /// Every sequence's run in lexicographic order, if it was asked for.
+    pub table: Option<Vec<AmpRun>>,
+}
+
+/// How the amplifiers are wired, and which phase settings to try.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AmpConfig {
+    pub amps: usize,
+    /// Candidate phase settings for every amplifier.
+    pub phases: Vec<i64>,
+    /// Whether more than one amplifier may share a phase setting.
+    pub repeat: bool,
+    /// The signal sent to the first amplifier.
+    pub initial_signal: i64,
+    /// Whether the last amplifier's output loops back to the first.
+    pub feedback: bool,
+}
+
+impl AmpConfig {
+    /// The series circuit from part 1 of day 7.
+    pub fn series() -> Self {
+        AmpConfig {
+            amps: 5,
+            phases: (0..5).collect(),
+            repeat: false,
+            initial_signal: 0,
+            feedback: false,
+        }
+    }
+
+    /// The feedback loop from part 2 of day 7.
+    pub fn feedback_loop() -> Self {
+        AmpConfig {
+            phases: (5..10).collect(),
+            feedback: true,
+            ..AmpConfig::series()
+        }
+    }
+
+    /// Every phase sequence the search tries.
+    pub fn sequences(&self) -> Sequences {
+        if self.repeat {
+            Sequences::product(&self.phases, self.amps)
+        } else {
+            Sequences::permutations(&self.phases, self.amps)
+        }
+    }
+
+    /// Runs the amplifiers with each given phase setting, ignoring `amps` and
+    /// `phases`. Fails with `Error::NoAmplifiers` if there are no phase settings,
+    /// and with `Error::NoOutput` if an amplifier halts without output.
+    pub fn run(&self, prog: &Intcode, phases: &[i64]) -> Result<AmpRun> {
+        if phases.is_empty() {
+            return Err(Error::NoAmplifiers);
+        }
+        let mut network = Network::new();
+        let amps: Vec<NodeId> = phases
+            .iter()
+            .enumerate()
+            .map(|(i, &phase)| network.add_machine(format!("Amp {}", i), prog.clone(), &[phase]))
+            .collect();
+        network.push_input(amps[0], self.initial_signal);
+        if self.feedback {
+            network.ring(&amps);
+        } else {
+            network.pipeline(&amps);
+        }
+        for &amp in amps.iter() {
+            network.collect(amp);
+        }
+        let output = network.run()?;
+        let signals = amps
+            .iter()
+            .enumerate()
+            .map(|(i, &amp)| {
+                output.last_output(amp).ok_or_else(|| Error::NoOutput {
+                    machine: format!("Amp {}", i),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(AmpRun {
+            phases: Vec::from(phases),
+            signals,
+        })
+    }
+
+    /// Tries every phase sequence, keeping every run if `keep_table` is set.
+    /// Fails with `Error::NoPhaseSequences` if there are none, such as when there
+    /// are more amplifiers than phases without `repeat`.
+    pub fn search(&self, prog: &Intcode, keep_table: bool) -> Result<AmpSearch> {
+        let state = Mutex::new(SearchState::default());
+        search::for_each_sequence(
+            &self.sequences(),
+            search::default_workers(),
+            |phases| self.run(prog, phases),
+            |_, run| {
+                let mut state = state.lock().unwrap();
+                let better = match &state.best {
+                    Some(best) => {
+                        (run.thruster(), Reverse(&run.phases))
+                            > (best.thruster(), Reverse(&best.phases))
+                    }
+                    None => true,
+                };
+                if better {
+                    state.best = Some(run.clone());
+                }
+                if keep_table {
+                    state.table.push(run);
+                }
+            },
+        )?;
+        let mut state = state.into_inner().unwrap();
+        state.table.sort_unstable_by(|a, b| a.phases.cmp(&b.phases));
+        Ok(AmpSearch {
+            best: state.best.ok_or(Error::NoPhaseSequences)?,
+            table: if keep_table { Some(state.table) } else { None },
+        })
+    }
+}
+
+/// Runs the amplifiers with each given phase setting, sending 0 to the first.
+/// With `feedback`, the last amplifier's output loops back to the first.
+pub fn run_amplifiers(prog: &Intcode, phases: &[i64], feedback: bool) -> Result<AmpRun> {
+    AmpConfig {
+        feedback,
+        ..AmpConfig::series()
+    }
+    .run(prog, phases)
+}
+
+#[derive(Default)]
+struct SearchState {
+    best: Option<AmpRun>,
+    table: Vec<AmpRun>,
+}
+
+/// Tries every permutation of `phases`, keeping every run if `keep_table` is set.
+pub fn search(
+    prog: &Intcode,
+    phases: &[i64],
+    feedback: bool,
+    keep_table: bool,
+) -> Result<AmpSearch> {
+    let config = AmpConfig {
+        amps: phases.len(),
+        phases: Vec::from(phases),
+        repeat: false,
+        initial_signal: 0,
+        feedback,
+    };
+    config.search(prog, keep_table)
+}
+
+fn json_list(values: &[i64]) -> String {
+    let values: Vec<String> = values.iter().map(i64::to_string).collect();
+    format!("[{}]", values.join(","))
+}
+
+fn json_run(run: &AmpRun) -> String {
+    format!(
+        "{{\"phases\":{},\"signals\":{},\"thruster\":{}}}",
+        json_list(&run.phases),
+        json_list(&run.signals),
+        run.thruster()
+    )
+}
+
+impl AmpSearch {
+    /// The table as CSV, with a column for each amplifier's phase and then the
+    /// thruster signal. Empty if there's no table.
+    pub fn table_csv(&self) -> String {
+        let mut csv = String::new();
+        let table = match &self.table {
+            Some(table) => table,
+            None => return csv,
+        };
+        for i in 0..self.best.phases.len() {
+            write!(csv, "amp{},", i).unwrap();
+        }
+        csv.push_str("thruster\n");
+        for run in table.iter() {
+            for phase in run.phases.iter() {
+                write!(csv, "{},", phase).unwrap();
+            }
+            writeln!(csv, "{}", run.thruster()).unwrap();
+        }
+        csv
+    }
+
+    /// The best run, and the table if there is one, as JSON.
+    pub fn to_json(&self) -> String {
+        let mut json = format!("{{\"best\":{}", json_run(&self.best));
+        if let Some(table) = &self.table {
+            let runs: Vec<String> = table.iter().map(json_run).collect();
+            write!(json, ",\"table\":[{}]", runs.join(",")).unwrap();
+        }
+        json.push('}');
+        json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_circuits() {
+        // Passes its signal on.
+        let echo = &Intcode::new(vec![3, 9, 3, 10, 4, 10, 99, 0, 0, 0, 0]);
+        let config = AmpConfig {
+            phases: Vec::new(),
+            ..AmpConfig::series()
+        };
+        assert!(matches!(config.run(echo, &[]), Err(Error::NoAmplifiers)));
+        assert!(matches!(
+            config.search(echo, false),
+            Err(Error::NoPhaseSequences)
+        ));
+        assert_eq!(config.run(echo, &[1, 2]).unwrap().signals, vec![0, 0]);
+
+        // Reads its phase and signal, then halts.
+        let silent = &Intcode::new(vec![3, 5, 3, 5, 99, 0]);
+        match AmpConfig::series().run(silent, &[0]) {
+            Err(Error::NoOutput { machine }) => assert_eq!(machine, "Amp 0"),
+            result => panic!("{:?}", result),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{Error, Intcode, Result, StepResult};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+/// Text and non-ASCII values collected from a program's output.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AsciiOutput {
+    /// Complete lines of text, without their trailing newline.
+    /// Any text left after the final newline is included as the last line.
+    pub lines: Vec<String>,
+
+    /// Outputs that aren't ASCII characters, such as a large final answer.
+    pub values: Vec<i64>,
+}
+
+/// Converts between lines of text and the character codes an Intcode program speaks.
+#[derive(Default)]
+pub struct Ascii {
+    input: VecDeque<i64>,
+    line: String,
+    output: AsciiOutput,
+}
+
+impl Ascii {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Queues a line of text as input, terminated by a newline.
+    pub fn push_line(&mut self, line: &str) {
+        self.input.extend(line.bytes().map(i64::from));
+        self.input.push_back(i64::from(b'\n'));
+    }
+
+    pub fn next_input(&mut self) -> Option<i64> {
+        self.input.pop_front()
+    }
+
+    /// Records an output value, returning the line it completes, if any.
+    pub fn push_output(&mut self, value: i64) -> Option<&str> {
+        match u8::try_from(value) {
+            Ok(b'\n') => {
+                let line = std::mem::take(&mut self.line);
+                self.output.lines.push(line);
+                self.output.lines.last().map(String::as_str)
+            }
+            Ok(c) if c.is_ascii() => {
+                self.line.push(char::from(c));
+                None
+            }
+            _ => {
+                self.output.values.push(value);
+                None
+            }
+        }
+    }
+
+    /// Text output since the last newline, such as a prompt.
+    pub fn partial_line(&self) -> &str {
+        &self.line
+    }
+
+    pub fn finish(mut self) -> AsciiOutput {
+        if !self.line.is_empty() {
+            self.output.lines.push(self.line);
+        }
+        self.output
+    }
+}
+
+/// Runs an ASCII program, asking `next_line` for a line of input whenever the
+/// program has consumed everything given so far, and passing each completed
+/// line of output to `on_line` as it's printed. Watchpoints that ask to pause
+/// don't stop the run.
+pub fn run_ascii<L, O>(prog: &mut Intcode, mut next_line: L, on_line: O) -> Result<AsciiOutput>
+where
+    L: FnMut(&str) -> String,
+    O: FnMut(&str),
+{
+    try_run_ascii(prog, |prompt| Some(next_line(prompt)), on_line)
+}
+
+/// Like `run_ascii`, but `next_line` returns `None` when there's no more
+/// input, which fails the run with `Error::InputExhausted` if the program
+/// asks for more.
+pub fn try_run_ascii<L, O>(
+    prog: &mut Intcode,
+    mut next_line: L,
+    mut on_line: O,
+) -> Result<AsciiOutput>
+where
+    L: FnMut(&str) -> Option<String>,
+    O: FnMut(&str),
+{
+    let ascii = RefCell::new(Ascii::new());
+    prog.reset();
+    loop {
+        let result = prog.try_step(
+            &mut || loop {
+                let mut ascii = ascii.borrow_mut();
+                if let Some(c) = ascii.next_input() {
+                    break Some(c);
+                }
+                let line = next_line(ascii.partial_line())?;
+                ascii.push_line(&line);
+            },
+            &mut |out| {
+                if let Some(line) = ascii.borrow_mut().push_output(out) {
+                    on_line(line);
+                }
+            },
+        )?;
+        match result {
+            StepResult::Continue | StepResult::Paused(_) => {}
+            StepResult::WaitingForInput => return Err(Error::InputExhausted { pc: prog.pc() }),
+            StepResult::Complete => return Ok(ascii.into_inner().finish()),
+        }
+    }
+}
+
+/// Runs an ASCII program with a fixed script of input lines.
+pub fn run_script(prog: &mut Intcode, script: &[&str]) -> Result<AsciiOutput> {
+    let mut script = script.iter();
+    try_run_ascii(prog, |_| script.next().map(|line| line.to_string()), |_| {})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{WatchAction, WatchKind};
+
+    #[test]
+    fn echo_line() {
+        // Echoes characters until a newline, then outputs 1234.
+        let prog = &mut Intcode::new(vec![
+            3, 14, 4, 14, 1008, 14, 10, 15, 1006, 15, 0, 104, 1234, 99, 0, 0,
+        ]);
+        let output = run_script(prog, &["hi there"]).unwrap();
+        assert_eq!(
+            output,
+            AsciiOutput {
+                lines: vec!["hi there".to_string()],
+                values: vec![1234],
+            }
+        );
+    }
+
+    #[test]
+    fn script_runs_out() {
+        // Reads two characters.
+        let prog = &mut Intcode::new(vec![3, 0, 3, 0, 99]);
+        match run_script(prog, &[]) {
+            Err(Error::InputExhausted { pc: 0 }) => {}
+            result => panic!("{:?}", result),
+        }
+        assert!(run_script(prog, &["a"]).is_ok());
+    }
+
+    #[test]
+    fn pauses_are_ignored() {
+        // Echoes characters until a newline, then outputs 1234.
+        let prog = &mut Intcode::new(vec![
+            3, 14, 4, 14, 1008, 14, 10, 15, 1006, 15, 0, 104, 1234, 99, 0, 0,
+        ]);
+        prog.watch(14..=14, WatchKind::Write, |_| WatchAction::Pause);
+        let output = run_script(prog, &["hi"]).unwrap();
+        assert_eq!(output.lines, vec!["hi"]);
+        assert_eq!(output.values, vec![1234]);
+    }
+
+    #[test]
+    fn partial_line() {
+        let mut ascii = Ascii::new();
+        for &c in b"ab\ncd" {
+            ascii.push_output(i64::from(c));
+        }
+        ascii.push_output(-1);
+        assert_eq!(ascii.partial_line(), "cd");
+        let output = ascii.finish();
+        assert_eq!(output.lines, vec!["ab", "cd"]);
+        assert_eq!(output.values, vec![-1]);
+    }
+}
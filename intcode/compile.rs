@@ -0,0 +1,939 @@
+//! A compiler from a small C-like language to Intcode.
+//!
+//! ```text
+//! let calls = 0;
+//!
+//! fn fib(n) {
+//!     calls = calls + 1;
+//!     if (n < 2) { return n; }
+//!     return fib(n - 1) + fib(n - 2);
+//! }
+//!
+//! fn main() {
+//!     let n = input();
+//!     while (n > 0) {
+//!         output(fib(n));
+//!         n = n - 1;
+//!     }
+//! }
+//! ```
+//!
+//! Values are integers, with `+ - * < > <= >= == != && || !` on them; there is
+//! no division. Comparisons and logic give 0 or 1, and `&&` and `||` only
+//! evaluate their right side when they need to. Globals are declared with
+//! integer initializers, and locals with `let` are scoped to their block.
+//! `input()` reads a value and `output(x)` writes one, giving 0. Functions
+//! return 0 if they don't return anything else, and the program runs `main`.
+//!
+//! Each call gets a frame on a stack after the program, addressed through the
+//! relative base: the return address, then the arguments, locals and
+//! temporaries. The callee leaves its result in the first argument's slot.
+
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    /// 1-based line of the problem.
+    pub line: usize,
+    /// 1-based column of the problem.
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Pos {
+    line: usize,
+    column: usize,
+}
+
+fn error<T>(pos: Pos, message: impl Into<String>) -> Result<T> {
+    Err(CompileError {
+        line: pos.line,
+        column: pos.column,
+        message: message.into(),
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    /// Punctuation and operators.
+    Symbol(&'static str),
+    End,
+}
+
+const SYMBOLS: &[&str] = &[
+    "<=", ">=", "==", "!=", "&&", "||", "(", ")", "{", "}", ",", ";", "=", "+", "-", "*", "!", "<",
+    ">",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, Pos)>> {
+    let mut tokens = Vec::new();
+    for (line_index, line) in source.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let pos = Pos {
+                line: line_index + 1,
+                column: i + 1,
+            };
+            let rest: String = chars[i..].iter().collect();
+            let c = chars[i];
+            if c.is_whitespace() {
+                i += 1;
+            } else if rest.starts_with("//") {
+                break;
+            } else if c.is_ascii_digit() {
+                let digits: String = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit())
+                    .collect();
+                i += digits.len();
+                match digits.parse() {
+                    Ok(n) => tokens.push((Token::Number(n), pos)),
+                    Err(_) => return error(pos, format!("{} is too large", digits)),
+                }
+            } else if c.is_alphabetic() || c == '_' {
+                let word: String = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_alphanumeric() || **c == '_')
+                    .collect();
+                i += word.chars().count();
+                tokens.push((Token::Ident(word), pos));
+            } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                i += symbol.len();
+                tokens.push((Token::Symbol(symbol), pos));
+            } else {
+                return error(pos, format!("unexpected {:?}", c));
+            }
+        }
+    }
+    let end = Pos {
+        line: source.lines().count().max(1),
+        column: source.lines().last().map_or(0, |l| l.chars().count()) + 1,
+    };
+    tokens.push((Token::End, end));
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+/// Operators by increasing precedence.
+const PRECEDENCE: &[&[(&str, BinOp)]] = &[
+    &[("||", BinOp::Or)],
+    &[("&&", BinOp::And)],
+    &[("==", BinOp::Equal), ("!=", BinOp::NotEqual)],
+    &[
+        ("<", BinOp::Less),
+        (">", BinOp::Greater),
+        ("<=", BinOp::LessEqual),
+        (">=", BinOp::GreaterEqual),
+    ],
+    &[("+", BinOp::Add), ("-", BinOp::Sub)],
+    &[("*", BinOp::Mul)],
+];
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(i64),
+    Var(String, Pos),
+    Call(String, Vec<Expr>, Pos),
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug)]
+enum Stmt {
+    Let(String, Expr),
+    Assign(String, Expr, Pos),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Expr(Expr),
+}
+
+struct Function {
+    name: String,
+    params: Vec<String>,
+    body: Vec<Stmt>,
+    pos: Pos,
+}
+
+struct Global {
+    name: String,
+    value: i64,
+    pos: Pos,
+}
+
+struct Parser {
+    tokens: Vec<(Token, Pos)>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].0
+    }
+
+    fn pos(&self) -> Pos {
+        self.tokens[self.next].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.next].0.clone();
+        if token != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Token::Symbol(s) if *s == symbol) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Token::Ident(word) if word == keyword => {
+                self.next += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<()> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            error(self.pos(), format!("expected {}", symbol))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        let pos = self.pos();
+        match self.advance() {
+            Token::Ident(name) if !KEYWORDS.contains(&name.as_str()) => Ok(name),
+            _ => error(pos, "expected a name"),
+        }
+    }
+
+    fn program(&mut self) -> Result<(Vec<Global>, Vec<Function>)> {
+        let mut globals = Vec::new();
+        let mut functions = Vec::new();
+        while *self.peek() != Token::End {
+            let pos = self.pos();
+            if self.eat_keyword("fn") {
+                let name = self.ident()?;
+                self.expect("(")?;
+                let mut params = Vec::new();
+                while !self.eat(")") {
+                    if !params.is_empty() {
+                        self.expect(",")?;
+                    }
+                    let pos = self.pos();
+                    let param = self.ident()?;
+                    if params.contains(&param) {
+                        return error(pos, format!("{} is defined twice", param));
+                    }
+                    params.push(param);
+                }
+                let body = self.block()?;
+                functions.push(Function {
+                    name,
+                    params,
+                    body,
+                    pos,
+                });
+            } else if self.eat_keyword("let") {
+                let name = self.ident()?;
+                let mut value = 0;
+                if self.eat("=") {
+                    let negative = self.eat("-");
+                    let pos = self.pos();
+                    value = match self.advance() {
+                        Token::Number(n) if negative => -n,
+                        Token::Number(n) => n,
+                        _ => return error(pos, "globals need an integer initializer"),
+                    };
+                }
+                self.expect(";")?;
+                globals.push(Global { name, value, pos });
+            } else {
+                return error(pos, "expected fn or let");
+            }
+        }
+        Ok((globals, functions))
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>> {
+        self.expect("{")?;
+        let mut stmts = Vec::new();
+        while !self.eat("}") {
+            stmts.push(self.statement()?);
+        }
+        Ok(stmts)
+    }
+
+    fn condition(&mut self) -> Result<Expr> {
+        self.expect("(")?;
+        let condition = self.expr(0)?;
+        self.expect(")")?;
+        Ok(condition)
+    }
+
+    fn statement(&mut self) -> Result<Stmt> {
+        if self.eat_keyword("let") {
+            let name = self.ident()?;
+            self.expect("=")?;
+            let value = self.expr(0)?;
+            self.expect(";")?;
+            Ok(Stmt::Let(name, value))
+        } else if self.eat_keyword("if") {
+            let condition = self.condition()?;
+            let then = self.block()?;
+            let otherwise = if !self.eat_keyword("else") {
+                Vec::new()
+            } else if matches!(self.peek(), Token::Ident(word) if word == "if") {
+                vec![self.statement()?]
+            } else {
+                self.block()?
+            };
+            Ok(Stmt::If(condition, then, otherwise))
+        } else if self.eat_keyword("while") {
+            let condition = self.condition()?;
+            Ok(Stmt::While(condition, self.block()?))
+        } else if self.eat_keyword("return") {
+            let value = if self.eat(";") {
+                None
+            } else {
+                let value = self.expr(0)?;
+                self.expect(";")?;
+                Some(value)
+            };
+            Ok(Stmt::Return(value))
+        } else {
+            let pos = self.pos();
+            let expr = self.expr(0)?;
+            if self.eat("=") {
+                let name = match expr {
+                    Expr::Var(name, _) => name,
+                    _ => return error(pos, "can only assign to a variable"),
+                };
+                let value = self.expr(0)?;
+                self.expect(";")?;
+                return Ok(Stmt::Assign(name, value, pos));
+            }
+            self.expect(";")?;
+            Ok(Stmt::Expr(expr))
+        }
+    }
+
+    /// Parses operators of at least precedence `level`.
+    fn expr(&mut self, level: usize) -> Result<Expr> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut left = self.expr(level + 1)?;
+        'operators: loop {
+            for &(symbol, op) in PRECEDENCE[level] {
+                if self.eat(symbol) {
+                    let right = self.expr(level + 1)?;
+                    left = Expr::Binary(op, Box::new(left), Box::new(right));
+                    continue 'operators;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat("-") {
+            return Ok(match self.unary()? {
+                Expr::Number(n) => Expr::Number(-n),
+                e => Expr::Negate(Box::new(e)),
+            });
+        }
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        let pos = self.pos();
+        match self.advance() {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Symbol("(") => {
+                let e = self.expr(0)?;
+                self.expect(")")?;
+                Ok(e)
+            }
+            Token::Ident(name) if !KEYWORDS.contains(&name.as_str()) => {
+                if !self.eat("(") {
+                    return Ok(Expr::Var(name, pos));
+                }
+                let mut args = Vec::new();
+                while !self.eat(")") {
+                    if !args.is_empty() {
+                        self.expect(",")?;
+                    }
+                    args.push(self.expr(0)?);
+                }
+                Ok(Expr::Call(name, args, pos))
+            }
+            _ => error(pos, "expected an expression"),
+        }
+    }
+}
+
+const KEYWORDS: &[&str] = &["fn", "let", "if", "else", "while", "return"];
+/// Functions every program has, which it can't define.
+const BUILTINS: &[&str] = &["input", "output"];
+
+/// A value in the output that may not be known until everything is compiled.
+#[derive(Clone, Copy, Debug)]
+enum Word {
+    Value(i64),
+    Label(usize),
+    /// The frame size of a function, times a scale, plus an offset.
+    Frame(usize, i64, i64),
+}
+
+/// An instruction parameter.
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Immediate(Word),
+    Position(Word),
+    /// A slot in the current frame.
+    Relative(Word),
+}
+
+impl Operand {
+    fn slot(slot: usize) -> Operand {
+        Operand::Relative(Word::Value(slot as i64))
+    }
+
+    fn mode(self) -> i64 {
+        match self {
+            Operand::Position(_) => 0,
+            Operand::Immediate(_) => 1,
+            Operand::Relative(_) => 2,
+        }
+    }
+
+    fn word(self) -> Word {
+        match self {
+            Operand::Position(w) | Operand::Immediate(w) | Operand::Relative(w) => w,
+        }
+    }
+}
+
+const ZERO: Operand = Operand::Immediate(Word::Value(0));
+
+struct Signature {
+    arity: usize,
+    label: usize,
+}
+
+#[derive(Default)]
+struct Codegen {
+    code: Vec<Word>,
+    labels: Vec<Option<usize>>,
+    frames: Vec<i64>,
+    functions: HashMap<String, Signature>,
+    globals: HashMap<String, usize>,
+    /// Names in scope in the current function, innermost last, with their
+    /// slots.
+    scopes: Vec<Vec<(String, usize)>>,
+    /// The next free slot in the current frame.
+    slot: usize,
+    /// The most slots the current function has used.
+    frame_size: usize,
+    function: usize,
+}
+
+impl Codegen {
+    fn label(&mut self) -> usize {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn place(&mut self, label: usize) {
+        self.labels[label] = Some(self.code.len());
+    }
+
+    fn emit(&mut self, opcode: i64, params: &[Operand]) {
+        let mut full = opcode;
+        let mut scale = 100;
+        for param in params {
+            full += param.mode() * scale;
+            scale *= 10;
+        }
+        self.code.push(Word::Value(full));
+        self.code.extend(params.iter().map(|p| p.word()));
+    }
+
+    fn copy(&mut self, from: Operand, to: Operand) {
+        self.emit(1, &[from, ZERO, to]);
+    }
+
+    fn jump(&mut self, label: usize) {
+        self.emit(6, &[ZERO, Operand::Immediate(Word::Label(label))]);
+    }
+
+    fn temp(&mut self) -> usize {
+        self.slot += 1;
+        self.frame_size = self.frame_size.max(self.slot);
+        self.slot - 1
+    }
+
+    fn lookup(&self, name: &str, pos: Pos) -> Result<Operand> {
+        for scope in self.scopes.iter().rev() {
+            if let Some((_, slot)) = scope.iter().rev().find(|(n, _)| n == name) {
+                return Ok(Operand::slot(*slot));
+            }
+        }
+        match self.globals.get(name) {
+            Some(&label) => Ok(Operand::Position(Word::Label(label))),
+            None => error(pos, format!("unknown variable {}", name)),
+        }
+    }
+
+    /// Evaluates `e`, possibly into a new temporary.
+    fn operand(&mut self, e: &Expr) -> Result<Operand> {
+        match e {
+            Expr::Number(n) => Ok(Operand::Immediate(Word::Value(*n))),
+            Expr::Var(name, pos) => self.lookup(name, *pos),
+            _ => {
+                let slot = self.temp();
+                self.expr_into(e, Operand::slot(slot))?;
+                Ok(Operand::slot(slot))
+            }
+        }
+    }
+
+    /// Evaluates `e` and stores it in `dest`.
+    fn expr_into(&mut self, e: &Expr, dest: Operand) -> Result<()> {
+        let start = self.slot;
+        match e {
+            Expr::Number(_) | Expr::Var(..) => {
+                let value = self.operand(e)?;
+                self.copy(value, dest);
+            }
+            Expr::Negate(e) => {
+                let value = self.operand(e)?;
+                self.emit(2, &[value, Operand::Immediate(Word::Value(-1)), dest]);
+            }
+            Expr::Not(e) => {
+                let value = self.operand(e)?;
+                self.emit(8, &[value, ZERO, dest]);
+            }
+            Expr::Binary(op @ BinOp::And, left, right)
+            | Expr::Binary(op @ BinOp::Or, left, right) => {
+                // Jumps to `short` with the result when the left side decides it.
+                let short = self.label();
+                let end = self.label();
+                let left = self.operand(left)?;
+                let (jump, result) = if *op == BinOp::And { (6, 0) } else { (5, 1) };
+                self.emit(jump, &[left, Operand::Immediate(Word::Label(short))]);
+                let right = self.operand(right)?;
+                let not = Operand::slot(self.temp());
+                self.emit(8, &[right, ZERO, not]);
+                self.emit(8, &[not, ZERO, dest]);
+                self.jump(end);
+                self.place(short);
+                self.copy(Operand::Immediate(Word::Value(result)), dest);
+                self.place(end);
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.operand(left)?;
+                let right = self.operand(right)?;
+                match op {
+                    BinOp::Add => self.emit(1, &[left, right, dest]),
+                    BinOp::Sub => match right {
+                        Operand::Immediate(Word::Value(n)) => {
+                            let negated = Operand::Immediate(Word::Value(n.wrapping_neg()));
+                            self.emit(1, &[left, negated, dest]);
+                        }
+                        _ => {
+                            let negated = Operand::slot(self.temp());
+                            let minus_one = Operand::Immediate(Word::Value(-1));
+                            self.emit(2, &[right, minus_one, negated]);
+                            self.emit(1, &[left, negated, dest]);
+                        }
+                    },
+                    BinOp::Mul => self.emit(2, &[left, right, dest]),
+                    BinOp::Less => self.emit(7, &[left, right, dest]),
+                    BinOp::Greater => self.emit(7, &[right, left, dest]),
+                    BinOp::Equal => self.emit(8, &[left, right, dest]),
+                    BinOp::LessEqual | BinOp::GreaterEqual | BinOp::NotEqual => {
+                        // The negation of the opposite comparison.
+                        let opposite = Operand::slot(self.temp());
+                        match op {
+                            BinOp::LessEqual => self.emit(7, &[right, left, opposite]),
+                            BinOp::GreaterEqual => self.emit(7, &[left, right, opposite]),
+                            _ => self.emit(8, &[left, right, opposite]),
+                        }
+                        self.emit(8, &[opposite, ZERO, dest]);
+                    }
+                    BinOp::And | BinOp::Or => unreachable!(),
+                }
+            }
+            Expr::Call(name, args, pos) => self.call(name, args, *pos, dest)?,
+        }
+        self.slot = start;
+        Ok(())
+    }
+
+    /// Calls a builtin or a function. Functions can't be named after builtins.
+    fn call(&mut self, name: &str, args: &[Expr], pos: Pos, dest: Operand) -> Result<()> {
+        match (name, args) {
+            ("input", []) => {
+                self.emit(3, &[dest]);
+                return Ok(());
+            }
+            ("output", [value]) => {
+                let value = self.operand(value)?;
+                self.emit(4, &[value]);
+                self.copy(ZERO, dest);
+                return Ok(());
+            }
+            ("input", _) | ("output", _) => {
+                return error(pos, format!("wrong number of arguments to {}", name))
+            }
+            _ => {}
+        }
+        let (arity, function) = match self.functions.get(name) {
+            Some(signature) => (signature.arity, signature.label),
+            None => return error(pos, format!("unknown function {}", name)),
+        };
+        if args.len() != arity {
+            return error(
+                pos,
+                format!("{} takes {} arguments, not {}", name, arity, args.len()),
+            );
+        }
+        // Evaluate every argument before storing any, since a call in a later
+        // argument would overwrite the callee's frame.
+        let mut values = Vec::new();
+        for arg in args {
+            values.push(self.operand(arg)?);
+        }
+        let caller = self.function;
+        let frame = |offset| Operand::Relative(Word::Frame(caller, 1, offset));
+        for (i, value) in values.into_iter().enumerate() {
+            self.copy(value, frame(1 + i as i64));
+        }
+        let back = self.label();
+        self.copy(Operand::Immediate(Word::Label(back)), frame(0));
+        self.emit(9, &[Operand::Immediate(Word::Frame(caller, 1, 0))]);
+        self.jump(function);
+        self.place(back);
+        let size = Word::Frame(caller, -1, 0);
+        self.emit(9, &[Operand::Immediate(size)]);
+        self.copy(frame(1), dest);
+        Ok(())
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) -> Result<()> {
+        self.scopes.push(Vec::new());
+        let start = self.slot;
+        for stmt in stmts {
+            self.statement(stmt)?;
+        }
+        self.scopes.pop();
+        self.slot = start;
+        Ok(())
+    }
+
+    fn condition(&mut self, condition: &Expr, jump: i64, label: usize) -> Result<()> {
+        let start = self.slot;
+        let value = self.operand(condition)?;
+        self.emit(jump, &[value, Operand::Immediate(Word::Label(label))]);
+        self.slot = start;
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<()> {
+        match stmt {
+            Stmt::Let(name, value) => {
+                let slot = self.temp();
+                self.expr_into(value, Operand::slot(slot))?;
+                self.scopes.last_mut().unwrap().push((name.clone(), slot));
+            }
+            Stmt::Assign(name, value, pos) => {
+                let dest = self.lookup(name, *pos)?;
+                self.expr_into(value, dest)?;
+            }
+            Stmt::If(condition, then, otherwise) => {
+                let skip = self.label();
+                let end = self.label();
+                self.condition(condition, 6, skip)?;
+                self.block(then)?;
+                if !otherwise.is_empty() {
+                    self.jump(end);
+                }
+                self.place(skip);
+                self.block(otherwise)?;
+                self.place(end);
+            }
+            Stmt::While(condition, body) => {
+                let top = self.label();
+                let check = self.label();
+                self.jump(check);
+                self.place(top);
+                self.block(body)?;
+                self.place(check);
+                self.condition(condition, 5, top)?;
+            }
+            Stmt::Return(value) => {
+                let value = match value {
+                    Some(value) => self.operand(value)?,
+                    None => ZERO,
+                };
+                self.ret(value);
+            }
+            Stmt::Expr(e) => {
+                let slot = self.temp();
+                self.expr_into(e, Operand::slot(slot))?;
+                self.slot = slot;
+            }
+        }
+        Ok(())
+    }
+
+    /// Leaves `value` in the first argument's slot and jumps to the return
+    /// address.
+    fn ret(&mut self, value: Operand) {
+        self.copy(value, Operand::slot(1));
+        self.emit(6, &[ZERO, Operand::slot(0)]);
+    }
+
+    fn function(&mut self, index: usize, function: &Function) -> Result<()> {
+        self.place(self.functions[&function.name].label);
+        self.function = index;
+        let params = function.params.iter().enumerate();
+        self.scopes = vec![params.map(|(i, p)| (p.clone(), i + 1)).collect()];
+        // The return address and result always need slots.
+        self.slot = 1 + function.params.len();
+        self.frame_size = self.slot.max(2);
+        for stmt in function.body.iter() {
+            self.statement(stmt)?;
+        }
+        self.ret(ZERO);
+        self.frames.push(self.frame_size as i64);
+        Ok(())
+    }
+
+    fn resolve(&self, word: Word) -> i64 {
+        match word {
+            Word::Value(n) => n,
+            Word::Label(label) => self.labels[label].unwrap() as i64,
+            Word::Frame(function, scale, offset) => self.frames[function] * scale + offset,
+        }
+    }
+}
+
+/// Compiles a program to Intcode that runs from address 0.
+pub fn compile(source: &str) -> Result<Vec<i64>> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        next: 0,
+    };
+    let (globals, functions) = parser.program()?;
+
+    let mut gen = Codegen::default();
+    for function in functions.iter() {
+        if BUILTINS.contains(&function.name.as_str()) {
+            return error(
+                function.pos,
+                format!("{} is a builtin function", function.name),
+            );
+        }
+        let signature = Signature {
+            arity: function.params.len(),
+            label: gen.label(),
+        };
+        if gen
+            .functions
+            .insert(function.name.clone(), signature)
+            .is_some()
+        {
+            return error(function.pos, format!("{} is defined twice", function.name));
+        }
+    }
+    for global in globals.iter() {
+        let label = gen.label();
+        if gen.globals.insert(global.name.clone(), label).is_some() {
+            return error(global.pos, format!("{} is defined twice", global.name));
+        }
+    }
+    let main = match gen.functions.get("main") {
+        Some(main) if main.arity == 0 => main.label,
+        _ => {
+            return error(
+                Pos { line: 1, column: 1 },
+                "no main function without arguments",
+            )
+        }
+    };
+
+    // The stack starts after the program, and main returns to a halt.
+    let stack = gen.label();
+    let halt = gen.label();
+    gen.emit(9, &[Operand::Immediate(Word::Label(stack))]);
+    gen.copy(Operand::Immediate(Word::Label(halt)), Operand::slot(0));
+    gen.jump(main);
+    gen.place(halt);
+    gen.emit(99, &[]);
+    for (index, function) in functions.iter().enumerate() {
+        gen.function(index, function)?;
+    }
+    for global in globals.iter() {
+        gen.place(gen.globals[&global.name]);
+        gen.code.push(Word::Value(global.value));
+    }
+    gen.place(stack);
+    Ok(gen.code.iter().map(|&word| gen.resolve(word)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Intcode;
+
+    fn run(source: &str, inputs: &[i64]) -> Vec<i64> {
+        let program = compile(source).unwrap_or_else(|e| panic!("{}", e));
+        let mut inputs = inputs.iter().copied();
+        let mut outputs = Vec::new();
+        Intcode::new(program)
+            .run_limited(
+                || inputs.next().unwrap(),
+                |out| outputs.push(out),
+                1_000_000,
+            )
+            .unwrap();
+        outputs
+    }
+
+    #[test]
+    fn programs() {
+        let fib = "
+            let calls = 0;
+
+            fn fib(n) {
+                calls = calls + 1;
+                if (n < 2) { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+
+            fn main() {
+                let n = input();
+                while (n > 0) {
+                    output(fib(n));
+                    n = n - 1;
+                }
+                output(calls);
+            }
+        ";
+        assert_eq!(run(fib, &[6]), vec![8, 5, 3, 2, 1, 1, 58]);
+
+        let operators = "
+            fn check(a, b) {
+                output(a - b * 2 + -a);
+                output((a < b) + (a > b) * 2 + (a <= b) * 4 + (a >= b) * 8);
+                output((a == b) + (a != b) * 2 + !a * 4);
+                output((a && b) + (a || b) * 2);
+            }
+
+            fn main() {
+                check(3, 4);
+                check(0, 0);
+                check(-5, 0);
+            }
+        ";
+        assert_eq!(
+            run(operators, &[]),
+            vec![-8, 5, 2, 3, 0, 12, 5, 0, 0, 5, 2, 2]
+        );
+
+        // Short circuits skip the calls with side effects.
+        let short = "
+            fn say(x) { output(x); return x; }
+
+            fn main() {
+                let a = say(0) && say(1);
+                let b = say(2) || say(3);
+                if (a) { output(10); } else if (b) { output(20); } else { output(30); }
+                let total = 0;
+                let i = 0;
+                while (i < 4) {
+                    let square = i * i;
+                    total = total + square;
+                    i = i + 1;
+                }
+                return output(total);
+            }
+        ";
+        assert_eq!(run(short, &[]), vec![0, 2, 20, 14]);
+    }
+
+    #[test]
+    fn errors() {
+        let err = |source: &str| {
+            let e = compile(source).unwrap_err();
+            (e.line, e.column, e.message)
+        };
+        assert_eq!(
+            err("fn main() {\n  x = 1;\n}"),
+            (2, 3, "unknown variable x".to_string())
+        );
+        assert_eq!(
+            err("fn f(a) {}\nfn main() { f(1, 2); }"),
+            (2, 13, "f takes 1 arguments, not 2".to_string())
+        );
+        assert_eq!(
+            err("fn main() { let = 1; }"),
+            (1, 17, "expected a name".to_string())
+        );
+        assert_eq!(err("fn main() { 1 $ 2; }").2, "unexpected '$'");
+        assert_eq!(err("fn f() {}").2, "no main function without arguments");
+        assert_eq!(
+            err("fn f(a, a) {}\nfn main() {}"),
+            (1, 9, "a is defined twice".to_string())
+        );
+        assert_eq!(
+            err("fn main() {}\nfn output(x) {}"),
+            (2, 1, "output is a builtin function".to_string())
+        );
+    }
+}
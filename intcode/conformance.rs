@@ -0,0 +1,429 @@
+use crate::{Error, Intcode, StepResult};
+
+/// Why a run stopped, in terms both interpreters can report.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stop {
+    Halted,
+    WaitingForInput,
+    UnknownOpcode,
+    InvalidMode,
+    NegativeAddress,
+    PcOutOfBounds,
+    StepLimit,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Outcome {
+    pub stop: Stop,
+    /// Where the machine stopped, which for errors is the failing instruction.
+    pub pc: usize,
+    pub outputs: Vec<i64>,
+    pub memory: Vec<i64>,
+}
+
+/// Runs a program on the main interpreter.
+pub fn run_main(program: &[i64], inputs: &[i64], max_steps: usize) -> Outcome {
+    let mut machine = Intcode::new(Vec::from(program));
+    let mut inputs = inputs.iter().copied();
+    let mut outputs = Vec::new();
+    let mut stop = Stop::StepLimit;
+    for _ in 0..max_steps {
+        let result = machine.try_step(&mut || inputs.next(), &mut |out| outputs.push(out));
+        stop = match result {
+            Ok(StepResult::Continue) | Ok(StepResult::Paused(_)) => continue,
+            Ok(StepResult::Complete) => Stop::Halted,
+            Ok(StepResult::WaitingForInput) => Stop::WaitingForInput,
+            Err(Error::UnknownOpcode { .. }) => Stop::UnknownOpcode,
+            Err(Error::InvalidParameterMode { .. }) => Stop::InvalidMode,
+            Err(Error::NegativeAddress { .. }) => Stop::NegativeAddress,
+            Err(Error::PcOutOfBounds(_)) => Stop::PcOutOfBounds,
+            Err(e) => panic!("unexpected error {:?}", e),
+        };
+        break;
+    }
+    Outcome {
+        stop,
+        pc: machine.pc(),
+        outputs,
+        memory: machine.memory().to_vec(),
+    }
+}
+
+/// A deliberately simple interpreter to check the main one against. It decodes
+/// every parameter from scratch and keeps memory in a plain `Vec`.
+struct Reference {
+    mem: Vec<i64>,
+    pc: usize,
+    base: i64,
+}
+
+impl Reference {
+    fn read(&self, address: usize) -> i64 {
+        self.mem.get(address).copied().unwrap_or(0)
+    }
+
+    fn mode(&self, n: usize) -> i64 {
+        self.mem[self.pc] / 10i64.pow(n as u32 + 1) % 10
+    }
+
+    fn address(&self, n: usize) -> Result<usize, Stop> {
+        let raw = self.read(self.pc + n);
+        let address = match self.mode(n) {
+            0 => raw,
+            2 => self.base.wrapping_add(raw),
+            _ => return Err(Stop::InvalidMode),
+        };
+        if address < 0 {
+            return Err(Stop::NegativeAddress);
+        }
+        Ok(address as usize)
+    }
+
+    fn param(&self, n: usize) -> Result<i64, Stop> {
+        match self.mode(n) {
+            1 => Ok(self.read(self.pc + n)),
+            _ => Ok(self.read(self.address(n)?)),
+        }
+    }
+
+    fn write(&mut self, address: usize, value: i64) {
+        if address >= self.mem.len() {
+            self.mem.resize(address + 1, 0);
+        }
+        self.mem[address] = value;
+    }
+
+    /// Executes one instruction, returning why the machine stopped if it did.
+    fn step(
+        &mut self,
+        inputs: &mut impl Iterator<Item = i64>,
+        outputs: &mut Vec<i64>,
+    ) -> Result<(), Stop> {
+        if self.pc >= self.mem.len() {
+            return Err(Stop::PcOutOfBounds);
+        }
+        match self.mem[self.pc] % 100 {
+            op @ 1 | op @ 2 | op @ 7 | op @ 8 => {
+                let (x, y) = (self.param(1)?, self.param(2)?);
+                let address = self.address(3)?;
+                let value = match op {
+                    1 => x.wrapping_add(y),
+                    2 => x.wrapping_mul(y),
+                    7 => (x < y) as i64,
+                    _ => (x == y) as i64,
+                };
+                self.write(address, value);
+                self.pc += 4;
+            }
+            3 => {
+                let address = self.address(1)?;
+                let value = inputs.next().ok_or(Stop::WaitingForInput)?;
+                self.write(address, value);
+                self.pc += 2;
+            }
+            4 => {
+                outputs.push(self.param(1)?);
+                self.pc += 2;
+            }
+            op @ 5 | op @ 6 => {
+                let (value, target) = (self.param(1)?, self.param(2)?);
+                if (value != 0) == (op == 5) {
+                    if target < 0 {
+                        return Err(Stop::PcOutOfBounds);
+                    }
+                    self.pc = target as usize;
+                } else {
+                    self.pc += 3;
+                }
+            }
+            9 => {
+                self.base = self.base.wrapping_add(self.param(1)?);
+                self.pc += 2;
+            }
+            99 => return Err(Stop::Halted),
+            _ => return Err(Stop::UnknownOpcode),
+        }
+        Ok(())
+    }
+}
+
+/// Runs a program on the reference interpreter.
+pub fn run_reference(program: &[i64], inputs: &[i64], max_steps: usize) -> Outcome {
+    let mut machine = Reference {
+        mem: Vec::from(program),
+        pc: 0,
+        base: 0,
+    };
+    let mut inputs = inputs.iter().copied();
+    let mut outputs = Vec::new();
+    let mut stop = Stop::StepLimit;
+    for _ in 0..max_steps {
+        if let Err(s) = machine.step(&mut inputs, &mut outputs) {
+            stop = s;
+            break;
+        }
+    }
+    Outcome {
+        stop,
+        pc: machine.pc,
+        outputs,
+        memory: machine.mem,
+    }
+}
+
+/// Runs a program on both interpreters, failing if they disagree.
+pub fn check(program: &[i64], inputs: &[i64], max_steps: usize) -> Outcome {
+    let main = run_main(program, inputs, max_steps);
+    let reference = run_reference(program, inputs, max_steps);
+    assert_eq!(
+        main, reference,
+        "interpreters disagree on {:?} with inputs {:?}",
+        program, inputs
+    );
+    main
+}
+
+struct Case {
+    name: &'static str,
+    program: Vec<i64>,
+    inputs: Vec<i64>,
+    outputs: Vec<i64>,
+    stop: Stop,
+    memory: Option<Vec<i64>>,
+}
+
+fn case(name: &'static str, program: &[i64], inputs: &[i64], outputs: &[i64]) -> Case {
+    Case {
+        name,
+        program: Vec::from(program),
+        inputs: Vec::from(inputs),
+        outputs: Vec::from(outputs),
+        stop: Stop::Halted,
+        memory: None,
+    }
+}
+
+impl Case {
+    fn memory(mut self, memory: &[i64]) -> Self {
+        self.memory = Some(Vec::from(memory));
+        self
+    }
+
+    fn stops(mut self, stop: Stop) -> Self {
+        self.stop = stop;
+        self
+    }
+}
+
+/// The published 2019 examples, and edge cases the puzzles leave implicit.
+fn corpus() -> Vec<Case> {
+    let compare = [
+        3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0,
+        1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20,
+        1105, 1, 46, 98, 99,
+    ];
+    let quine = [
+        109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+    ];
+    vec![
+        case(
+            "day 2 example",
+            &[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50],
+            &[],
+            &[],
+        )
+        .memory(&[3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50]),
+        case("day 2 add", &[1, 0, 0, 0, 99], &[], &[]).memory(&[2, 0, 0, 0, 99]),
+        case("day 2 multiply", &[2, 3, 0, 3, 99], &[], &[]).memory(&[2, 3, 0, 6, 99]),
+        case("day 2 multiply far", &[2, 4, 4, 5, 99, 0], &[], &[]).memory(&[2, 4, 4, 5, 99, 9801]),
+        case("day 2 overwrite", &[1, 1, 1, 4, 99, 5, 6, 0, 99], &[], &[])
+            .memory(&[30, 1, 1, 4, 2, 5, 6, 0, 99]),
+        case("day 5 echo", &[3, 0, 4, 0, 99], &[42], &[42]),
+        case("day 5 modes", &[1002, 4, 3, 4, 33], &[], &[]).memory(&[1002, 4, 3, 4, 99]),
+        case("day 5 negative", &[1101, 100, -1, 4, 0], &[], &[]).memory(&[1101, 100, -1, 4, 99]),
+        case(
+            "day 5 equal position",
+            &[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8],
+            &[8],
+            &[1],
+        ),
+        case(
+            "day 5 less position",
+            &[3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8],
+            &[5],
+            &[1],
+        ),
+        case(
+            "day 5 equal immediate",
+            &[3, 3, 1108, -1, 8, 3, 4, 3, 99],
+            &[7],
+            &[0],
+        ),
+        case(
+            "day 5 less immediate",
+            &[3, 3, 1107, -1, 8, 3, 4, 3, 99],
+            &[9],
+            &[0],
+        ),
+        case(
+            "day 5 jump position",
+            &[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+            &[0],
+            &[0],
+        ),
+        case(
+            "day 5 jump immediate",
+            &[3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1],
+            &[5],
+            &[1],
+        ),
+        case("day 5 compare below", &compare, &[7], &[999]),
+        case("day 5 compare equal", &compare, &[8], &[1000]),
+        case("day 5 compare above", &compare, &[9], &[1001]),
+        case(
+            "day 7 first",
+            &[
+                3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+            ],
+            &[4, 0],
+            &[4],
+        ),
+        case(
+            "day 7 second",
+            &[
+                3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4,
+                23, 99, 0, 0,
+            ],
+            &[1, 5],
+            &[54],
+        ),
+        case(
+            "day 7 third",
+            &[
+                3, 31, 3, 32, 1002, 32, 10, 32, 1001, 31, -2, 31, 1007, 31, 0, 33, 1002, 33, 7, 33,
+                1, 33, 31, 31, 1, 32, 31, 31, 4, 31, 99, 0, 0, 0,
+            ],
+            &[1, 0],
+            &[6],
+        ),
+        case(
+            "day 7 feedback",
+            &[
+                3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28,
+                -1, 28, 1005, 28, 6, 99, 0, 0, 5,
+            ],
+            &[9, 0],
+            &[5],
+        )
+        .stops(Stop::WaitingForInput),
+        case("day 9 quine", &quine, &[], &quine),
+        case(
+            "day 9 sixteen digits",
+            &[1102, 34915192, 34915192, 7, 4, 7, 99, 0],
+            &[],
+            &[1219070632396864],
+        ),
+        case(
+            "day 9 large",
+            &[104, 1125899906842624, 99],
+            &[],
+            &[1125899906842624],
+        ),
+        case(
+            "day 9 relative read",
+            &[109, 2000, 109, 19, 204, -34, 99],
+            &[],
+            &[0],
+        ),
+        case(
+            "relative write",
+            &[109, 10, 21101, 5, 6, 0, 204, 0, 99],
+            &[],
+            &[11],
+        )
+        .memory(&[109, 10, 21101, 5, 6, 0, 204, 0, 99, 0, 11]),
+        case(
+            "relative input",
+            &[109, 5, 203, 2, 204, 2, 99],
+            &[77],
+            &[77],
+        ),
+        case("unknown opcode", &[42], &[], &[]).stops(Stop::UnknownOpcode),
+        case("immediate write", &[11101, 1, 1, 5, 99], &[], &[]).stops(Stop::InvalidMode),
+        case("invalid mode", &[301, 0, 0, 0, 99], &[], &[]).stops(Stop::InvalidMode),
+        case("negative address", &[1, -1, 0, 0, 99], &[], &[]).stops(Stop::NegativeAddress),
+        case("negative relative", &[109, -5, 204, 0, 99], &[], &[]).stops(Stop::NegativeAddress),
+        case("negative jump", &[1105, 1, -5], &[], &[]).stops(Stop::PcOutOfBounds),
+        case("off the end", &[1, 0, 0, 0], &[], &[]).stops(Stop::PcOutOfBounds),
+        case("no input", &[3, 0, 99], &[], &[]).stops(Stop::WaitingForInput),
+        case("endless", &[1105, 1, 0], &[], &[]).stops(Stop::StepLimit),
+    ]
+}
+
+#[test]
+fn published_examples() {
+    for case in corpus() {
+        let outcome = check(&case.program, &case.inputs, 10_000);
+        assert_eq!(outcome.stop, case.stop, "{}", case.name);
+        assert_eq!(outcome.outputs, case.outputs, "{}", case.name);
+        if let Some(memory) = &case.memory {
+            assert_eq!(&outcome.memory, memory, "{}", case.name);
+        }
+    }
+}
+
+#[test]
+fn random_programs() {
+    // Xorshift, so failures reproduce.
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let mut next = |n: u64| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state % n
+    };
+    const OPCODES: [i64; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+    for _ in 0..3000 {
+        let len = 8 + next(32) as usize;
+        let program: Vec<i64> = (0..len)
+            .map(|_| match next(3) {
+                0 => {
+                    let modes =
+                        (0..3).fold(0, |modes, _| modes * 10 + [0, 0, 1, 2, 3][next(5) as usize]);
+                    modes * 100 + OPCODES[next(10) as usize]
+                }
+                _ => next(len as u64 + 8) as i64 - 4,
+            })
+            .collect();
+        let inputs: Vec<i64> = (0..next(4)).map(|_| next(10) as i64 - 2).collect();
+        check(&program, &inputs, 500);
+    }
+}
+
+#[test]
+fn compiled_programs() {
+    // Collatz stopping times, with recursion deep enough to grow memory well
+    // past the image.
+    let source = "
+        fn steps(n) {
+            if (n == 1) { return 0; }
+            let half = 0;
+            while (half + half < n) { half = half + 1; }
+            if (half + half == n) { return 1 + steps(half); }
+            return 1 + steps(3 * n + 1);
+        }
+
+        fn main() {
+            let n = input();
+            while (n != 0) {
+                output(steps(n));
+                n = input();
+            }
+        }
+    ";
+    let program = crate::compile::compile(source).unwrap();
+    let outcome = check(&program, &[1, 6, 27, 0], 1_000_000);
+    assert_eq!(outcome.stop, Stop::Halted);
+    assert_eq!(outcome.outputs, vec![0, 8, 111]);
+    assert!(outcome.memory.len() > program.len() + 111 * 3);
+}
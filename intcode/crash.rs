@@ -0,0 +1,73 @@
+use crate::{Error, Intcode, MemoryDiff};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Inputs and outputs kept for each machine in a crash report.
+const HISTORY: usize = 16;
+
+/// The most recent inputs and outputs of one machine.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct IoHistory {
+    inputs: VecDeque<i64>,
+    outputs: VecDeque<i64>,
+}
+
+fn remember(values: &mut VecDeque<i64>, value: i64) {
+    if values.len() == HISTORY {
+        values.pop_front();
+    }
+    values.push_back(value);
+}
+
+impl IoHistory {
+    pub fn input(&mut self, value: i64) {
+        remember(&mut self.inputs, value);
+    }
+
+    pub fn output(&mut self, value: i64) {
+        remember(&mut self.outputs, value);
+    }
+
+    /// Wraps an error from the machine `index` of a multi-machine run.
+    pub fn crash(&self, name: &str, index: usize, machine: &Intcode, error: Error) -> Error {
+        Error::Machine(Box::new(MachineError {
+            name: name.to_string(),
+            index,
+            pc: machine.pc(),
+            last_inputs: self.inputs.iter().copied().collect(),
+            last_outputs: self.outputs.iter().copied().collect(),
+            diff: machine.diff(),
+            error,
+        }))
+    }
+}
+
+/// An error from one machine of a multi-machine run, with its state when it
+/// failed.
+#[derive(Debug)]
+pub struct MachineError {
+    pub name: String,
+    /// The machine's position in the run, such as a `MachineId`.
+    pub index: usize,
+    pub pc: usize,
+    /// The last few inputs read, oldest first.
+    pub last_inputs: Vec<i64>,
+    /// The last few outputs written, oldest first.
+    pub last_outputs: Vec<i64>,
+    /// Memory changes from the program image.
+    pub diff: MemoryDiff,
+    pub error: Error,
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} (machine {}) failed at pc {}: {:?}",
+            self.name, self.index, self.pc, self.error
+        )?;
+        writeln!(f, "last inputs: {:?}", self.last_inputs)?;
+        writeln!(f, "last outputs: {:?}", self.last_outputs)?;
+        write!(f, "memory changes: {}", self.diff)
+    }
+}
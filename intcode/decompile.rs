@@ -0,0 +1,707 @@
+//! Turns a program back into structured pseudo-code.
+//!
+//! Code is found by tracing from address 0 and from the return addresses of
+//! calls, then by disassembling the gaps between what the trace reached, so
+//! code that's only reached after the program patches itself still shows up.
+//! Forward jumps become `if`/`else`, backward jumps other than calls become
+//! loops, and jumps to a loop's end or start become `break` and `continue`;
+//! anything else is a `goto`. Cells that code reads
+//! or writes by position become variables named by their address.
+
+use crate::disasm::{self, Decoded, Param};
+use crate::isa::{InstructionSet, Mode, Op};
+use crate::Memory;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+enum Node {
+    Statement(usize, String),
+    If {
+        address: usize,
+        condition: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    /// A loop that runs `body` while `condition` holds, testing it first, or
+    /// forever if there's no condition.
+    While {
+        address: usize,
+        condition: Option<String>,
+        body: Vec<Node>,
+    },
+    DoWhile {
+        address: usize,
+        body: Vec<Node>,
+        condition: String,
+    },
+    /// Marks the start of code the trace from address 0 didn't reach.
+    Unreached,
+}
+
+/// Where `break` and `continue` jump to in the innermost loop.
+#[derive(Clone, Copy, Default)]
+struct Loop {
+    head: Option<usize>,
+    exit: Option<usize>,
+}
+
+struct Decompiler<'a> {
+    memory: &'a Memory,
+    code: Vec<Decoded>,
+    reached: Vec<bool>,
+    /// The index in `code` of each instruction's address.
+    index: BTreeMap<usize, usize>,
+    /// Cells that are part of instructions.
+    code_cells: BTreeSet<usize>,
+    /// For each address, the backward jumps to it, by index.
+    back_jumps: BTreeMap<usize, Vec<usize>>,
+    gotos: BTreeSet<usize>,
+}
+
+fn negate(condition: &str) -> String {
+    match condition.strip_prefix('!') {
+        Some(inner) if !inner.contains(' ') => inner.to_string(),
+        _ if condition.contains(' ') => format!("!({})", condition),
+        _ => format!("!{}", condition),
+    }
+}
+
+impl<'a> Decompiler<'a> {
+    fn new(isa: &dyn InstructionSet, memory: &'a Memory) -> Self {
+        let trace = disasm::trace_program(isa, memory);
+        let mut data: BTreeSet<usize> = BTreeSet::new();
+        for decoded in trace.instructions.values() {
+            for param in decoded.params.iter() {
+                if param.mode == Mode::Position && param.value >= 0 {
+                    data.insert(param.value as usize);
+                }
+            }
+        }
+        let mut found: BTreeMap<usize, (Decoded, bool)> = trace
+            .instructions
+            .into_iter()
+            .map(|(address, decoded)| (address, (decoded, true)))
+            .collect();
+        let mut covered: BTreeSet<usize> = found
+            .values()
+            .flat_map(|(decoded, _)| decoded.address..decoded.next())
+            .collect();
+        // Disassemble what the trace missed, leaving out cells used as data.
+        let mut address = 0;
+        while address < memory.len() {
+            let free = |a: &usize| !covered.contains(a) && !data.contains(a) && *a < memory.len();
+            match disasm::decode(isa, memory, address) {
+                Some(decoded) if (address..decoded.next()).all(|a| free(&a)) => {
+                    covered.extend(address..decoded.next());
+                    address = decoded.next();
+                    found.insert(decoded.address, (decoded, false));
+                }
+                _ => address += 1,
+            }
+        }
+
+        let (code, reached): (Vec<Decoded>, Vec<bool>) = found.into_values().unzip();
+        let index = code
+            .iter()
+            .enumerate()
+            .map(|(i, decoded)| (decoded.address, i))
+            .collect();
+        let mut back_jumps: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (i, decoded) in code.iter().enumerate() {
+            if let Some(target) = jump_target(decoded) {
+                if target <= decoded.address && !is_call(&code, i) {
+                    back_jumps.entry(target).or_default().push(i);
+                }
+            }
+        }
+        Decompiler {
+            memory,
+            code,
+            reached,
+            index,
+            code_cells: covered,
+            back_jumps,
+            gotos: BTreeSet::new(),
+        }
+    }
+
+    fn operand(&self, param: Param) -> String {
+        match param.mode {
+            Mode::Immediate => param.value.to_string(),
+            Mode::Relative => format!("rb[{}]", param.value),
+            Mode::Position
+                if param.value >= 0 && self.code_cells.contains(&(param.value as usize)) =>
+            {
+                format!("mem[{}]", param.value)
+            }
+            Mode::Position => format!("v{}", param.value),
+        }
+    }
+
+    /// The condition under which a jump is taken.
+    fn jump_condition(&self, decoded: &Decoded) -> String {
+        let (condition, if_nonzero, _) = decoded.branch().unwrap();
+        let condition = self.operand(condition);
+        if if_nonzero {
+            condition
+        } else {
+            negate(&condition)
+        }
+    }
+
+    fn statement(&self, decoded: &Decoded) -> String {
+        let p = |n: usize| self.operand(decoded.params[n]);
+        let imm = |n: usize, value: i64| {
+            decoded.params[n]
+                == Param {
+                    mode: Mode::Immediate,
+                    value,
+                }
+        };
+        let assign = |value: String| format!("{} = {};", p(decoded.params.len() - 1), value);
+        match &decoded.op {
+            Op::Add if imm(1, 0) => assign(p(0)),
+            Op::Add if imm(0, 0) => assign(p(1)),
+            Op::Add => match (decoded.params[0], decoded.params[1]) {
+                (
+                    _,
+                    Param {
+                        mode: Mode::Immediate,
+                        value,
+                    },
+                ) if value < 0 => assign(format!("{} - {}", p(0), value.unsigned_abs())),
+                (
+                    Param {
+                        mode: Mode::Immediate,
+                        value,
+                    },
+                    _,
+                ) if value < 0 => assign(format!("{} - {}", p(1), value.unsigned_abs())),
+                _ => assign(format!("{} + {}", p(0), p(1))),
+            },
+            Op::Multiply if imm(1, 1) => assign(p(0)),
+            Op::Multiply if imm(1, -1) => assign(format!("-{}", p(0))),
+            Op::Multiply => assign(format!("{} * {}", p(0), p(1))),
+            Op::LessThan => assign(format!("{} < {}", p(0), p(1))),
+            Op::Equals if imm(1, 0) => assign(negate(&p(0))),
+            Op::Equals => assign(format!("{} == {}", p(0), p(1))),
+            Op::Input => assign("input()".to_string()),
+            Op::Output => format!("output({});", p(0)),
+            Op::AdjustRelativeBase => format!("rb += {};", p(0)),
+            Op::Halt => "halt;".to_string(),
+            Op::JumpIfTrue | Op::JumpIfFalse => {
+                let target = match jump_target(decoded) {
+                    Some(target) => format!("L{}", target),
+                    None => format!("*{}", p(1)),
+                };
+                match decoded.always() {
+                    Some(true) => format!("goto {};", target),
+                    Some(false) => "// never jumps".to_string(),
+                    None => format!("if ({}) goto {};", self.jump_condition(decoded), target),
+                }
+            }
+            Op::Custom(_) => {
+                let params: Vec<String> = (0..decoded.params.len()).map(p).collect();
+                format!("{}({});", decoded.name, params.join(", "))
+            }
+        }
+    }
+
+    /// The address just past the instructions in `range`, or the end of memory.
+    fn end_address(&self, end: usize) -> usize {
+        self.code
+            .get(end)
+            .map_or(usize::MAX, |decoded| decoded.address)
+    }
+
+    /// Structures the instructions with indexes in `start..end`.
+    fn structure(&mut self, start: usize, end: usize, within: Loop) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        let mut i = start;
+        while i < end {
+            let decoded = self.code[i].clone();
+            let address = decoded.address;
+            if !self.reached[i] && (i == start || self.reached[i - 1]) {
+                nodes.push(Node::Unreached);
+            }
+            // The last backward jump here in range closes a loop.
+            let closing = self.back_jumps.get(&address).and_then(|jumps| {
+                let in_range = jumps.iter().filter(|&&j| j >= i && j < end);
+                in_range.max().copied()
+            });
+            if let Some(j) = closing {
+                let jump = self.code[j].clone();
+                let inner = Loop {
+                    head: Some(address),
+                    exit: Some(jump.next()),
+                };
+                let body = self.structure(i, j, inner);
+                nodes.push(match jump.always() {
+                    Some(true) => Node::While {
+                        address,
+                        condition: None,
+                        body,
+                    },
+                    _ => Node::DoWhile {
+                        address,
+                        body,
+                        condition: self.jump_condition(&jump),
+                    },
+                });
+                i = j + 1;
+                continue;
+            }
+            let target = match (decoded.branch(), jump_target(&decoded)) {
+                (Some(_), Some(target)) if decoded.always() != Some(false) => target,
+                _ => {
+                    nodes.push(Node::Statement(address, self.statement(&decoded)));
+                    i += 1;
+                    continue;
+                }
+            };
+            let always = decoded.always() == Some(true);
+            let jump = if Some(target) == within.exit {
+                Some("break;")
+            } else if Some(target) == within.head {
+                Some("continue;")
+            } else {
+                None
+            };
+            if let Some(jump) = jump {
+                let statement = Node::Statement(address, jump.to_string());
+                nodes.push(if always {
+                    statement
+                } else {
+                    Node::If {
+                        address,
+                        condition: self.jump_condition(&decoded),
+                        then: vec![statement],
+                        otherwise: Vec::new(),
+                    }
+                });
+                i += 1;
+                continue;
+            }
+            let forward = target > address && target <= self.end_address(end);
+            let target_index = if target == self.end_address(end) {
+                Some(end)
+            } else {
+                self.index.get(&target).copied()
+            };
+            match target_index {
+                Some(t) if forward && always => {
+                    // A loop entered at its test: jumps forward to a test that
+                    // jumps back to just after this jump.
+                    let test = (t..end).find(|&c| {
+                        jump_target(&self.code[c]) == Some(decoded.next())
+                            && self.code[c].branch().is_some()
+                            && self.code[c].always().is_none()
+                    });
+                    match test {
+                        Some(c) if t < end && i + 1 < end => {
+                            let exit = self.code[c].next();
+                            let inner = Loop {
+                                head: Some(target),
+                                exit: Some(exit),
+                            };
+                            let condition = self.jump_condition(&self.code[c].clone());
+                            let mut body = self.structure(t, c, inner);
+                            let loop_body = self.structure(i + 1, t, inner);
+                            let condition = if body.is_empty() {
+                                Some(condition)
+                            } else {
+                                body.push(Node::If {
+                                    address: self.code[c].address,
+                                    condition: negate(&condition),
+                                    then: vec![Node::Statement(
+                                        self.code[c].address,
+                                        "break;".to_string(),
+                                    )],
+                                    otherwise: Vec::new(),
+                                });
+                                None
+                            };
+                            body.extend(loop_body);
+                            nodes.push(Node::While {
+                                address,
+                                condition,
+                                body,
+                            });
+                            i = c + 1;
+                        }
+                        _ => {
+                            self.gotos.insert(target);
+                            nodes.push(Node::Statement(address, self.statement(&decoded)));
+                            i += 1;
+                        }
+                    }
+                }
+                Some(t) if forward => {
+                    // An if, with an else if the then branch ends by jumping
+                    // over it.
+                    let mut then_end = t;
+                    let mut after = t;
+                    if t > i + 1 {
+                        let last = &self.code[t - 1];
+                        if let (Some(true), Some(e)) = (last.always(), jump_target(last)) {
+                            let exits = Some(e) == within.exit || Some(e) == within.head;
+                            let e_index = if e == self.end_address(end) {
+                                Some(end)
+                            } else {
+                                self.index.get(&e).copied()
+                            };
+                            if let Some(e_index) = e_index.filter(|&x| x > t && x <= end && !exits)
+                            {
+                                then_end = t - 1;
+                                after = e_index;
+                            }
+                        }
+                    }
+                    let condition = negate(&self.jump_condition(&decoded));
+                    let then = self.structure(i + 1, then_end, within);
+                    let otherwise = self.structure(t, after, within);
+                    nodes.push(Node::If {
+                        address,
+                        condition,
+                        then,
+                        otherwise,
+                    });
+                    i = after;
+                }
+                _ => {
+                    self.gotos.insert(target);
+                    nodes.push(Node::Statement(address, self.statement(&decoded)));
+                    i += 1;
+                }
+            }
+        }
+        nodes
+    }
+
+    /// Renders `nodes`, labelling the first line for each address in `labels`.
+    fn render(&self, nodes: &[Node], depth: usize, labels: &mut BTreeSet<usize>, out: &mut String) {
+        let indent = "    ".repeat(depth + 1);
+        let line =
+            |out: &mut String, labels: &mut BTreeSet<usize>, address: Option<usize>, text: &str| {
+                if let Some(a) = address.filter(|a| labels.remove(a)) {
+                    writeln!(out, "{:>5}  L{}:", "", a).unwrap();
+                }
+                match address {
+                    Some(a) => writeln!(out, "{:>5}: {}{}", a, indent, text).unwrap(),
+                    None => writeln!(out, "{:>5}  {}{}", "", indent, text).unwrap(),
+                }
+            };
+        for node in nodes {
+            match node {
+                Node::Statement(address, text) => line(out, labels, Some(*address), text),
+                Node::Unreached => line(out, labels, None, "// not reached from address 0"),
+                Node::If {
+                    address,
+                    condition,
+                    then,
+                    otherwise,
+                } => {
+                    line(
+                        out,
+                        labels,
+                        Some(*address),
+                        &format!("if ({}) {{", condition),
+                    );
+                    self.render(then, depth + 1, labels, out);
+                    if !otherwise.is_empty() {
+                        line(out, labels, None, "} else {");
+                        self.render(otherwise, depth + 1, labels, out);
+                    }
+                    line(out, labels, None, "}");
+                }
+                Node::While {
+                    address,
+                    condition,
+                    body,
+                } => {
+                    let condition = condition.as_deref().unwrap_or("true");
+                    line(
+                        out,
+                        labels,
+                        Some(*address),
+                        &format!("while ({}) {{", condition),
+                    );
+                    self.render(body, depth + 1, labels, out);
+                    line(out, labels, None, "}");
+                }
+                Node::DoWhile {
+                    address,
+                    body,
+                    condition,
+                } => {
+                    line(out, labels, Some(*address), "do {");
+                    self.render(body, depth + 1, labels, out);
+                    line(out, labels, None, &format!("}} while ({});", condition));
+                }
+            }
+        }
+    }
+}
+
+/// Whether the jump at `code[i]` is a call: it stores the address after the
+/// jump just past the relative base, then moves the base there.
+fn is_call(code: &[Decoded], i: usize) -> bool {
+    if i < 2 || code[i].always() != Some(true) {
+        return false;
+    }
+    let (store, adjust) = (&code[i - 2], &code[i - 1]);
+    let offset = match (&adjust.op, adjust.params[0]) {
+        (
+            Op::AdjustRelativeBase,
+            Param {
+                mode: Mode::Immediate,
+                value,
+            },
+        ) => value,
+        _ => return false,
+    };
+    let return_address = Param {
+        mode: Mode::Immediate,
+        value: code[i].next() as i64,
+    };
+    store.written()
+        == Some(Param {
+            mode: Mode::Relative,
+            value: offset,
+        })
+        && matches!(store.op, Op::Add)
+        && store.params[..2].contains(&return_address)
+        && store.params[..2].contains(&Param {
+            mode: Mode::Immediate,
+            value: 0,
+        })
+        && store.next() == adjust.address
+        && adjust.next() == code[i].address
+}
+
+/// The immediate target of a jump.
+fn jump_target(decoded: &Decoded) -> Option<usize> {
+    match decoded.branch()? {
+        (
+            _,
+            _,
+            Param {
+                mode: Mode::Immediate,
+                value,
+            },
+        ) if value >= 0 => Some(value as usize),
+        _ => None,
+    }
+}
+
+/// Decompiles a program to pseudo-code, with the address of each statement in
+/// the margin.
+pub fn decompile(isa: &dyn InstructionSet, memory: &Memory) -> String {
+    let mut decompiler = Decompiler::new(isa, memory);
+    let nodes = decompiler.structure(0, decompiler.code.len(), Loop::default());
+
+    let mut out = String::new();
+    let mut variables = BTreeSet::new();
+    for decoded in decompiler.code.iter() {
+        for param in decoded.params.iter() {
+            let address = param.value as usize;
+            if param.mode == Mode::Position
+                && param.value >= 0
+                && !decompiler.code_cells.contains(&address)
+            {
+                variables.insert(address);
+            }
+        }
+    }
+    for address in variables {
+        let value = decompiler.memory.get(address).unwrap_or(0);
+        writeln!(out, "{:>5}: let v{} = {};", address, address, value).unwrap();
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    let mut labels = decompiler.gotos.clone();
+    decompiler.render(&nodes, 0, &mut labels, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::compile;
+    use crate::isa::Profile;
+
+    fn decompiled(program: &[i64]) -> Vec<String> {
+        let text = decompile(&Profile::Full, &Memory::from_slice(program));
+        text.lines()
+            .map(|line| line.trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn structures() {
+        // Reads n, then outputs whether each of n down to 1 equals 1, in a
+        // loop entered at its test.
+        let program = vec![
+            3, 36, // in [36]
+            1105, 1, 27, // goto 27
+            1002, 36, 1, 37, // mul [36], 1 -> [37]
+            1008, 37, 1, 38, // eq [37], 1 -> [38]
+            1006, 38, 21, // jz [38], 21
+            104, 1, // out 1
+            1105, 1, 23, // goto 23
+            104, 0, // out 0
+            101, -1, 36, 36, // add -1, [36] -> [36]
+            1007, 36, 1, 39, // lt [36], 1 -> [39]
+            1006, 39, 5, // jz [39], 5
+            99, 0, 3, 0, 0, 0,
+        ];
+        assert_eq!(
+            decompiled(&program),
+            vec![
+                "   36: let v36 = 3;",
+                "   37: let v37 = 0;",
+                "   38: let v38 = 0;",
+                "   39: let v39 = 0;",
+                "",
+                "    0:     v36 = input();",
+                "    2:     while (true) {",
+                "   27:         v39 = v36 < 1;",
+                "   31:         if (v39) {",
+                "   31:             break;",
+                "               }",
+                "    5:         v37 = v36;",
+                "    9:         v38 = v37 == 1;",
+                "   13:         if (v38) {",
+                "   16:             output(1);",
+                "               } else {",
+                "   21:             output(0);",
+                "               }",
+                "   23:         v36 = v36 - 1;",
+                "           }",
+                "   34:     halt;",
+            ]
+        );
+
+        // An if in a loop whose then branch jumps past the end of the loop.
+        let program = vec![
+            3, 100, // in [100]
+            1006, 100, 10, // jz [100], 10
+            104, 1, // out 1
+            1105, 1, 17, // goto 17
+            104, 2, // out 2
+            1005, 100, 2, // jnz [100], 2
+            104, 3, // out 3
+            99,
+        ];
+        assert_eq!(
+            decompiled(&program),
+            vec![
+                "  100: let v100 = 0;",
+                "",
+                "    0:     v100 = input();",
+                "    2:     do {",
+                "    2:         if (v100) {",
+                "    5:             output(1);",
+                "    7:             goto L17;",
+                "               }",
+                "   10:         output(2);",
+                "           } while (v100);",
+                "   15:     output(3);",
+                "       L17:",
+                "   17:     halt;",
+            ]
+        );
+
+        // A loop whose head is also the target of a goto.
+        let program = vec![
+            1105, 1, 5, // goto 5
+            104, 1, // out 1
+            104, 2, // out 2
+            1005, 100, 5, // jnz [100], 5
+            99,
+        ];
+        assert_eq!(
+            decompiled(&program)[2..],
+            [
+                "    0:     goto L5;",
+                "           // not reached from address 0",
+                "    3:     output(1);",
+                "       L5:",
+                "    5:     do {",
+                "    5:         output(2);",
+                "           } while (v100);",
+                "   10:     halt;",
+            ]
+        );
+    }
+
+    #[test]
+    fn compiled_loops() {
+        let source = "
+            fn main() {
+                let i = 0;
+                while (i < 3) {
+                    output(i);
+                    i = i + 1;
+                }
+            }
+        ";
+        let text = decompile(
+            &Profile::Full,
+            &Memory::from_slice(&compile(source).unwrap()),
+        );
+        // Only the call to main is left as a goto to a label.
+        assert_eq!(text.matches("goto L").count(), 1, "{}", text);
+        assert!(text.contains("rb[2] = rb[1] < 3;"), "{}", text);
+        assert!(text.contains("if (!rb[2]) {"), "{}", text);
+        assert!(text.contains("output(rb[1]);"), "{}", text);
+    }
+
+    #[test]
+    fn compiled_calls() {
+        let source = "
+            fn inc(x) { return x + 1; }
+            fn add3(x) { return inc(inc(inc(x))); }
+            fn fact(n) {
+                let r = 1;
+                while (n > 1) {
+                    r = r * n;
+                    n = n - 1;
+                }
+                return r;
+            }
+            fn main() {
+                let i = 0;
+                while (i < 3) {
+                    output(add3(fact(i)));
+                    i = i + 1;
+                }
+            }
+        ";
+        let text = decompile(
+            &Profile::Full,
+            &Memory::from_slice(&compile(source).unwrap()),
+        );
+        let lines: Vec<&str> = text.lines().collect();
+        // Calls back to earlier functions are gotos, not loops, and code after
+        // a call is reached through its return address.
+        for line in &[
+            "       L10:",
+            "   10:     rb[2] = rb[1] + 1;",
+            "   38:     goto L10;",
+            "   57:     goto L10;",
+            "   76:     goto L10;",
+            "  103:     while (true) {",
+            "  139:     while (true) {",
+            "  152:         goto L99;",
+            "  171:         goto L28;",
+            "  180:         output(rb[3]);",
+        ] {
+            assert!(lines.contains(line), "{:?} in\n{}", line, text);
+        }
+        assert_eq!(text.matches("while (true) {").count(), 2, "{}", text);
+        // Only the fallback returns after each explicit one are unreached.
+        assert_eq!(text.matches("// not reached").count(), 3, "{}", text);
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+use std::ops::Range;
+
+/// A run of consecutive cells that changed, with their values before and after.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedRange {
+    pub start: usize,
+    pub before: Vec<i64>,
+    pub after: Vec<i64>,
+}
+
+impl ChangedRange {
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.start + self.after.len()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryDiff {
+    pub ranges: Vec<ChangedRange>,
+}
+
+impl MemoryDiff {
+    /// Compares two memory images. Cells past the end of the shorter one count as 0.
+    pub fn between(before: &[i64], after: &[i64]) -> Self {
+        let mut ranges: Vec<ChangedRange> = Vec::new();
+        for index in 0..before.len().max(after.len()) {
+            let old = before.get(index).copied().unwrap_or(0);
+            let new = after.get(index).copied().unwrap_or(0);
+            if old == new {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(last) if last.range().end == index => {
+                    last.before.push(old);
+                    last.after.push(new);
+                }
+                _ => ranges.push(ChangedRange {
+                    start: index,
+                    before: vec![old],
+                    after: vec![new],
+                }),
+            }
+        }
+        MemoryDiff { ranges }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn changed_cells(&self) -> usize {
+        self.ranges.iter().map(|r| r.after.len()).sum()
+    }
+}
+
+impl fmt::Display for MemoryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} cells changed in {} ranges",
+            self.changed_cells(),
+            self.ranges.len()
+        )?;
+        for r in self.ranges.iter() {
+            if let ([before], [after]) = (r.before.as_slice(), r.after.as_slice()) {
+                writeln!(f, "  {}: {} -> {}", r.start, before, after)?;
+            } else {
+                let range = r.range();
+                writeln!(
+                    f,
+                    "  {}..{}: {:?} -> {:?}",
+                    range.start, range.end, r.before, r.after
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        let diff = MemoryDiff::between(&[1, 9, 10, 3, 2, 3, 11, 5, 99], &[3500, 9, 10, 70, 71, 3]);
+        assert_eq!(
+            diff.ranges,
+            vec![
+                ChangedRange {
+                    start: 0,
+                    before: vec![1],
+                    after: vec![3500],
+                },
+                ChangedRange {
+                    start: 3,
+                    before: vec![3, 2],
+                    after: vec![70, 71],
+                },
+                ChangedRange {
+                    start: 6,
+                    before: vec![11, 5, 99],
+                    after: vec![0, 0, 0],
+                },
+            ]
+        );
+        assert_eq!(diff.changed_cells(), 6);
+        assert_eq!(
+            diff.to_string(),
+            "6 cells changed in 3 ranges\n  0: 1 -> 3500\n  3..5: [3, 2] -> [70, 71]\n  \
+             6..9: [11, 5, 99] -> [0, 0, 0]\n"
+        );
+    }
+
+    #[test]
+    fn new_machine_is_unchanged() {
+        let prog = crate::Intcode::new(vec![1, 0, 0, 0, 99]);
+        assert_eq!(prog.memory().to_vec(), vec![1, 0, 0, 0, 99]);
+        assert!(prog.diff().is_empty());
+    }
+}
@@ -0,0 +1,303 @@
+use crate::isa::{InstructionSet, Mode, Op};
+use crate::Memory;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Range;
+
+/// A parameter as it's written in the program.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Param {
+    pub mode: Mode,
+    pub value: i64,
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            Mode::Position => write!(f, "[{}]", self.value),
+            Mode::Immediate => write!(f, "{}", self.value),
+            Mode::Relative if self.value < 0 => write!(f, "[rb{}]", self.value),
+            Mode::Relative => write!(f, "[rb+{}]", self.value),
+        }
+    }
+}
+
+/// An instruction decoded from memory.
+#[derive(Clone)]
+pub struct Decoded {
+    pub address: usize,
+    /// The opcode with its mode digits.
+    pub opcode: i64,
+    pub name: String,
+    pub op: Op,
+    pub params: Vec<Param>,
+}
+
+impl Decoded {
+    /// The address just past the instruction's parameters.
+    pub fn next(&self) -> usize {
+        self.address + 1 + self.params.len()
+    }
+
+    /// For a jump, its condition, whether it jumps when the condition is
+    /// nonzero, and its target.
+    pub fn branch(&self) -> Option<(Param, bool, Param)> {
+        match self.op {
+            Op::JumpIfTrue => Some((self.params[0], true, self.params[1])),
+            Op::JumpIfFalse => Some((self.params[0], false, self.params[1])),
+            _ => None,
+        }
+    }
+
+    /// For a jump with an immediate condition, whether it always or never
+    /// jumps.
+    pub fn always(&self) -> Option<bool> {
+        match self.branch()? {
+            (
+                Param {
+                    mode: Mode::Immediate,
+                    value,
+                },
+                if_nonzero,
+                _,
+            ) => Some((value != 0) == if_nonzero),
+            _ => None,
+        }
+    }
+
+    /// The parameter the instruction writes to, for built-in instructions.
+    pub fn written(&self) -> Option<Param> {
+        match self.op {
+            Op::Add | Op::Multiply | Op::LessThan | Op::Equals => Some(self.params[2]),
+            Op::Input => Some(self.params[0]),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for Decoded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.address, self)
+    }
+}
+
+impl fmt::Display for Decoded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for (i, param) in self.params.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " " } else { ", " }, param)?;
+        }
+        Ok(())
+    }
+}
+
+/// A cell in a disassembly: an instruction, or a value that isn't one.
+#[derive(Clone, Debug)]
+pub enum Item {
+    Instruction(Decoded),
+    Data { address: usize, value: i64 },
+}
+
+impl Item {
+    pub fn address(&self) -> usize {
+        match self {
+            Item::Instruction(decoded) => decoded.address,
+            Item::Data { address, .. } => *address,
+        }
+    }
+
+    /// The address of the item after this one.
+    pub fn next(&self) -> usize {
+        match self {
+            Item::Instruction(decoded) => decoded.next(),
+            Item::Data { address, .. } => address + 1,
+        }
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Item::Instruction(decoded) => write!(f, "{}", decoded),
+            Item::Data { value, .. } => write!(f, "data {}", value),
+        }
+    }
+}
+
+/// The mode digit of parameter `n`, counting from 1.
+pub fn mode_digit(opcode: i64, n: usize) -> i64 {
+    10i64
+        .checked_pow(n as u32 + 1)
+        .map_or(0, |scale| opcode / scale % 10)
+}
+
+/// Decodes the instruction at `address`, or `None` if the value there isn't an
+/// opcode of `isa` with modes it allows. Parameters past the end of memory
+/// read as 0, as they do when the machine runs.
+pub fn decode(isa: &dyn InstructionSet, memory: &Memory, address: usize) -> Option<Decoded> {
+    let opcode = memory.get(address)?;
+    let instruction = isa.instruction(opcode % 100)?;
+    let mut params = Vec::new();
+    for n in 1..=instruction.arity() {
+        let mode = Mode::from_digit(mode_digit(opcode, n))
+            .filter(|&mode| instruction.modes().contains(mode))?;
+        let value = memory.get(address + n).unwrap_or(0);
+        params.push(Param { mode, value });
+    }
+    Some(Decoded {
+        address,
+        opcode,
+        name: instruction.name().to_string(),
+        op: instruction.op().clone(),
+        params,
+    })
+}
+
+/// Disassembles `range` in one pass from its start, treating anything that
+/// doesn't decode as data.
+pub fn disassemble(isa: &dyn InstructionSet, memory: &Memory, range: Range<usize>) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut address = range.start;
+    while address < range.end.min(memory.len()) {
+        let item = match decode(isa, memory, address) {
+            Some(decoded) => Item::Instruction(decoded),
+            None => Item::Data {
+                address,
+                value: memory[address],
+            },
+        };
+        address = item.next();
+        items.push(item);
+    }
+    items
+}
+
+/// The instructions reachable from an entry point, following jumps whose
+/// targets are immediate.
+#[derive(Debug, Default)]
+pub struct Trace {
+    pub instructions: BTreeMap<usize, Decoded>,
+    /// Reachable addresses that don't hold an instruction the set can decode.
+    pub invalid: BTreeSet<usize>,
+    /// Jumps with targets computed at runtime, so what they reach is unknown.
+    pub computed_jumps: BTreeSet<usize>,
+}
+
+impl Trace {
+    /// Whether `address` is part of a reachable instruction.
+    pub fn covers(&self, address: usize) -> bool {
+        self.instructions
+            .range(..=address)
+            .next_back()
+            .is_some_and(|(_, decoded)| address < decoded.next())
+    }
+
+    /// Adds the instructions reachable from `entry`.
+    pub fn extend(&mut self, isa: &dyn InstructionSet, memory: &Memory, entry: usize) {
+        let mut pending = vec![entry];
+        while let Some(address) = pending.pop() {
+            if self.instructions.contains_key(&address) || self.invalid.contains(&address) {
+                continue;
+            }
+            let decoded = match decode(isa, memory, address) {
+                Some(decoded) => decoded,
+                None => {
+                    self.invalid.insert(address);
+                    continue;
+                }
+            };
+            match (&decoded.op, decoded.branch()) {
+                (Op::Halt, _) => {}
+                (_, Some((_, _, target))) => {
+                    let always = decoded.always();
+                    if always != Some(true) {
+                        pending.push(decoded.next());
+                    }
+                    match target {
+                        _ if always == Some(false) => {}
+                        Param {
+                            mode: Mode::Immediate,
+                            value,
+                        } => {
+                            // Negative targets fail at runtime instead.
+                            if value >= 0 {
+                                pending.push(value as usize);
+                            }
+                        }
+                        _ => {
+                            self.computed_jumps.insert(address);
+                        }
+                    }
+                }
+                _ => pending.push(decoded.next()),
+            }
+            self.instructions.insert(address, decoded);
+        }
+    }
+}
+
+/// Traces the instructions reachable from `entry`, assuming the program
+/// doesn't modify its own code.
+pub fn trace(isa: &dyn InstructionSet, memory: &Memory, entry: usize) -> Trace {
+    let mut trace = Trace::default();
+    trace.extend(isa, memory, entry);
+    trace
+}
+
+/// Traces from address 0, and from the return addresses of calls if any jump
+/// is computed. A return address is taken to be the address after an
+/// unconditional jump that the program uses as an immediate value.
+pub fn trace_program(isa: &dyn InstructionSet, memory: &Memory) -> Trace {
+    let mut trace = trace(isa, memory, 0);
+    while !trace.computed_jumps.is_empty() {
+        let after_jumps: BTreeSet<usize> = trace
+            .instructions
+            .values()
+            .filter(|decoded| decoded.always() == Some(true))
+            .map(|decoded| decoded.next())
+            .collect();
+        let returns: Vec<usize> = trace
+            .instructions
+            .values()
+            .flat_map(|decoded| decoded.params.iter())
+            .filter(|param| param.mode == Mode::Immediate && param.value >= 0)
+            .map(|param| param.value as usize)
+            .filter(|address| after_jumps.contains(address))
+            .filter(|address| {
+                !trace.instructions.contains_key(address) && !trace.invalid.contains(address)
+            })
+            .collect();
+        if returns.is_empty() {
+            break;
+        }
+        for address in returns {
+            trace.extend(isa, memory, address);
+        }
+    }
+    trace
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::isa::Profile;
+
+    #[test]
+    fn listing() {
+        let memory = Memory::from_slice(&[1001, 9, -5, 9, 204, -3, 3, 99, 12345]);
+        let listing: Vec<String> = disassemble(&Profile::Full, &memory, 0..memory.len())
+            .iter()
+            .map(|item| format!("{}: {}", item.address(), item))
+            .collect();
+        assert_eq!(
+            listing,
+            vec![
+                "0: add [9], -5, [9]",
+                "4: out [rb-3]",
+                "6: in [99]",
+                "8: data 12345",
+            ]
+        );
+        assert!(decode(&Profile::Day2, &memory, 0).is_none());
+    }
+}
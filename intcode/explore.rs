@@ -0,0 +1,211 @@
+use crate::{Error, Intcode, Memory, Result, StepResult};
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Strategy {
+    BreadthFirst,
+    DepthFirst,
+}
+
+/// A machine stopped at an input instruction or halted, with the inputs that
+/// led there.
+#[derive(Clone)]
+pub struct Node {
+    pub machine: Intcode,
+    /// Inputs from the start of the search, one per fork.
+    pub inputs: Vec<i64>,
+    /// Everything output since the start of the search.
+    pub outputs: Vec<i64>,
+    /// Where the outputs for the last input start in `outputs`.
+    last_start: usize,
+    pub halted: bool,
+}
+
+impl Node {
+    /// Outputs produced in response to the last input.
+    pub fn last_outputs(&self) -> &[i64] {
+        &self.outputs[self.last_start..]
+    }
+}
+
+/// A key for nodes whose machines are in the same state: the pc, relative base
+/// and memory.
+pub fn machine_state(node: &Node) -> (usize, i64, Memory) {
+    let machine = &node.machine;
+    (
+        machine.pc(),
+        machine.relative_base(),
+        machine.memory().clone(),
+    )
+}
+
+pub struct Exploration {
+    /// The first node reached whose last outputs satisfied the goal.
+    pub found: Option<Node>,
+    /// Distinct states reached, including the start.
+    pub visited: usize,
+}
+
+/// Searches the inputs of a program, forking a machine each time it asks for
+/// input and trying every value of an alphabet on a separate fork.
+pub struct Explorer {
+    alphabet: Vec<i64>,
+    strategy: Strategy,
+    max_depth: Option<usize>,
+    max_steps: usize,
+}
+
+impl Explorer {
+    pub fn new(alphabet: &[i64]) -> Self {
+        Explorer {
+            alphabet: Vec::from(alphabet),
+            strategy: Strategy::BreadthFirst,
+            max_depth: None,
+            max_steps: 1_000_000,
+        }
+    }
+
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Stops forking nodes that have had `max_depth` inputs.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Fails with `Error::StepLimitExceeded` if a fork runs for more than
+    /// `max_steps` instructions without asking for input. Defaults to a million.
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Runs `node` until it asks for input it doesn't have, or halts.
+    fn advance(&self, node: &mut Node, mut input: Option<i64>) -> Result<()> {
+        let Node {
+            machine, outputs, ..
+        } = node;
+        for _ in 0..self.max_steps {
+            match machine.try_step(&mut || input.take(), &mut |out| outputs.push(out))? {
+                StepResult::Continue | StepResult::Paused(_) => {}
+                StepResult::Complete => {
+                    node.halted = true;
+                    return Ok(());
+                }
+                StepResult::WaitingForInput => return Ok(()),
+            }
+        }
+        Err(Error::StepLimitExceeded {
+            pc: machine.pc(),
+            steps: self.max_steps,
+        })
+    }
+
+    /// Explores from the machine's current state until a node's last outputs
+    /// satisfy `goal`. Nodes with the same `key` are only expanded once.
+    pub fn explore<K, Key, Goal>(
+        &self,
+        machine: &Intcode,
+        mut key: Key,
+        mut goal: Goal,
+    ) -> Result<Exploration>
+    where
+        K: Hash + Eq,
+        Key: FnMut(&Node) -> K,
+        Goal: FnMut(&[i64]) -> bool,
+    {
+        let mut start = Node {
+            machine: machine.clone(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            last_start: 0,
+            halted: false,
+        };
+        self.advance(&mut start, None)?;
+        let mut seen = HashSet::new();
+        seen.insert(key(&start));
+        if goal(start.last_outputs()) {
+            return Ok(Exploration {
+                found: Some(start),
+                visited: 1,
+            });
+        }
+        let mut frontier = VecDeque::new();
+        frontier.push_back(start);
+        while let Some(node) = match self.strategy {
+            Strategy::BreadthFirst => frontier.pop_front(),
+            Strategy::DepthFirst => frontier.pop_back(),
+        } {
+            if node.halted || Some(node.inputs.len()) == self.max_depth {
+                continue;
+            }
+            let mut children = Vec::new();
+            for &x in self.alphabet.iter() {
+                let mut child = node.clone();
+                child.inputs.push(x);
+                child.last_start = child.outputs.len();
+                self.advance(&mut child, Some(x))?;
+                if !seen.insert(key(&child)) {
+                    continue;
+                }
+                if goal(child.last_outputs()) {
+                    return Ok(Exploration {
+                        found: Some(child),
+                        visited: seen.len(),
+                    });
+                }
+                children.push(child);
+            }
+            // Depth first tries the alphabet in order too.
+            if self.strategy == Strategy::DepthFirst {
+                children.reverse();
+            }
+            frontier.extend(children);
+        }
+        Ok(Exploration {
+            found: None,
+            visited: seen.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_inputs() {
+        // Adds up its inputs, outputting 1 and halting once they reach 5, or 0.
+        let prog = Intcode::new(vec![
+            3, 21, 1, 21, 22, 22, 1008, 22, 5, 23, 1005, 23, 18, 104, 0, 1105, 1, 0, 104, 1, 99, 0,
+            0, 0,
+        ]);
+        let total = |node: &Node| node.machine.memory()[22];
+        let found = |exploration: Exploration| exploration.found.unwrap().inputs;
+
+        let explorer = Explorer::new(&[2, 3]);
+        let bfs = explorer.explore(&prog, machine_state, |out| out == [1]);
+        assert_eq!(found(bfs.unwrap()), vec![2, 3]);
+
+        let dfs = Explorer::new(&[2, 3])
+            .strategy(Strategy::DepthFirst)
+            .max_depth(3);
+        let dfs = dfs.explore(&prog, machine_state, |out| out == [1]);
+        assert_eq!(found(dfs.unwrap()), vec![2, 3]);
+
+        let exploration = Explorer::new(&[1, 2])
+            .explore(&prog, total, |out| out == [1])
+            .unwrap();
+        assert_eq!(exploration.visited, 6);
+        assert_eq!(found(exploration), vec![1, 2, 2]);
+
+        let none = Explorer::new(&[2]).max_depth(5);
+        let none = none.explore(&prog, total, |out| out == [1]).unwrap();
+        assert!(none.found.is_none());
+        assert_eq!(none.visited, 6);
+    }
+}
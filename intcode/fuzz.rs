@@ -0,0 +1,353 @@
+use crate::disasm::{self, Param};
+use crate::isa::{Mode, Op};
+use crate::{Error, Intcode, StepResult};
+use std::collections::{BTreeSet, HashSet};
+use std::convert::TryFrom;
+use std::mem;
+
+/// Which pcs were executed and which ways branches went.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Coverage {
+    pub pcs: BTreeSet<usize>,
+    /// Jumps (opcodes 5 and 6) with whether they jumped, and comparisons (7 and
+    /// 8) with whether they were true, by pc.
+    pub branches: BTreeSet<(usize, bool)>,
+}
+
+impl Coverage {
+    /// Adds `other`, returning whether it covered anything new.
+    pub fn merge(&mut self, other: &Coverage) -> bool {
+        let before = (self.pcs.len(), self.branches.len());
+        self.pcs.extend(other.pcs.iter().copied());
+        self.branches.extend(other.branches.iter().copied());
+        before != (self.pcs.len(), self.branches.len())
+    }
+}
+
+#[derive(Debug)]
+pub enum FindingKind {
+    Error(Error),
+    /// The run executed the target pc.
+    Target,
+}
+
+#[derive(Debug)]
+pub struct Finding {
+    /// The inputs the run read, which reproduce the finding.
+    pub inputs: Vec<i64>,
+    pub kind: FindingKind,
+}
+
+#[derive(Debug, Default)]
+pub struct FuzzReport {
+    pub runs: usize,
+    pub coverage: Coverage,
+    /// Inputs that each reached new coverage, in the order they were found.
+    pub corpus: Vec<Vec<i64>>,
+    /// The first inputs for each kind of error at each pc, and for the target.
+    pub findings: Vec<Finding>,
+}
+
+/// A xorshift64* generator, so runs can be reproduced from a seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+struct Execution {
+    coverage: Coverage,
+    /// How many inputs the run read before it stopped.
+    consumed: usize,
+    /// The error, and the pc of the instruction that failed.
+    error: Option<(Error, usize)>,
+    reached_target: bool,
+}
+
+/// Feeds generated inputs to a program, mutating the inputs that reach new
+/// coverage to look for more.
+///
+/// Each run reads inputs until it halts, fails, or asks for more than it was
+/// given. Mutations draw on the constants in the program image, so comparisons
+/// against magic values are quick to satisfy.
+pub struct Fuzzer {
+    runs: usize,
+    seed: u64,
+    max_steps: usize,
+    max_inputs: usize,
+    target: Option<usize>,
+    seeds: Vec<Vec<i64>>,
+}
+
+impl Default for Fuzzer {
+    fn default() -> Self {
+        Fuzzer {
+            runs: 10_000,
+            seed: 0x9e37_79b9_7f4a_7c15,
+            max_steps: 100_000,
+            max_inputs: 64,
+            target: None,
+            seeds: vec![vec![]],
+        }
+    }
+}
+
+impl Fuzzer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn runs(mut self, runs: usize) -> Self {
+        self.runs = runs;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        // Xorshift gets stuck at zero.
+        self.seed = seed.max(1);
+        self
+    }
+
+    /// Reports runs that execute more than `max_steps` instructions as
+    /// `Error::StepLimitExceeded`.
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn max_inputs(mut self, max_inputs: usize) -> Self {
+        self.max_inputs = max_inputs;
+        self
+    }
+
+    /// Reports the first inputs that execute the instruction at `pc`.
+    pub fn target(mut self, pc: usize) -> Self {
+        self.target = Some(pc);
+        self
+    }
+
+    /// Adds inputs to start mutating from.
+    pub fn add_seed(mut self, inputs: &[i64]) -> Self {
+        self.seeds.push(Vec::from(inputs));
+        self
+    }
+
+    fn execute(&self, prog: &Intcode, inputs: &[i64]) -> Execution {
+        let mut machine = prog.clone();
+        let mut coverage = Coverage::default();
+        let mut consumed = 0;
+        let mut reached_target = false;
+        let mut error = None;
+        for steps in 0.. {
+            let pc = machine.pc();
+            if steps == self.max_steps {
+                error = Some((Error::StepLimitExceeded { pc, steps }, pc));
+                break;
+            }
+            coverage.pcs.insert(pc);
+            reached_target |= self.target == Some(pc);
+            let decoded = disasm::decode(&**machine.instruction_set(), machine.memory(), pc);
+            // Whether a jump goes is decided before it runs, since it may jump
+            // to the next instruction anyway.
+            let jumped = decoded
+                .as_ref()
+                .and_then(|decoded| decoded.branch())
+                .map(|(condition, if_nonzero, _)| (load(&machine, condition) != 0) == if_nonzero);
+            let compared = decoded
+                .as_ref()
+                .filter(|decoded| matches!(decoded.op, Op::LessThan | Op::Equals))
+                .and_then(|decoded| address(&machine, decoded.params[2]));
+            let result = machine.try_step(
+                &mut || {
+                    let x = inputs.get(consumed).copied();
+                    consumed += x.is_some() as usize;
+                    x
+                },
+                &mut |_| {},
+            );
+            match result {
+                Ok(StepResult::Continue) | Ok(StepResult::Paused(_)) => {}
+                Ok(StepResult::Complete) | Ok(StepResult::WaitingForInput) => break,
+                Err(e) => {
+                    error = Some((e, pc));
+                    break;
+                }
+            }
+            if let Some(jumped) = jumped {
+                coverage.branches.insert((pc, jumped));
+            }
+            if let Some(value) = compared.and_then(|address| machine.memory().get(address)) {
+                coverage.branches.insert((pc, value != 0));
+            }
+        }
+        Execution {
+            coverage,
+            consumed,
+            error,
+            reached_target,
+        }
+    }
+
+    fn mutate(&self, rng: &mut Rng, dictionary: &[i64], inputs: &mut Vec<i64>) {
+        let mut value = || match rng.below(3) {
+            0 => dictionary[rng.below(dictionary.len())],
+            1 => rng.below(21) as i64 - 10,
+            _ => rng.next() as i64,
+        };
+        let value = value();
+        let at = rng.below(inputs.len() + 1);
+        match rng.below(4) {
+            0 if at < inputs.len() => inputs[at] = value,
+            1 if at < inputs.len() => {
+                inputs.remove(at);
+            }
+            2 if at < inputs.len() => inputs[at] = inputs[at].wrapping_add(value % 4),
+            _ if inputs.len() < self.max_inputs => inputs.insert(at, value),
+            _ => {}
+        }
+    }
+
+    pub fn run(&self, prog: &Intcode) -> FuzzReport {
+        let mut prog = prog.clone();
+        prog.reset();
+
+        let mut dictionary: Vec<i64> = prog
+            .program()
+            .initial_memory()
+            .iter()
+            .chain(-1..=1)
+            .collect();
+        dictionary.sort_unstable();
+        dictionary.dedup();
+
+        let mut rng = Rng(self.seed);
+        let mut report = FuzzReport::default();
+        let mut errors = HashSet::new();
+        let mut queue = self.seeds.clone();
+        while report.runs < self.runs {
+            let mut inputs = match queue.pop() {
+                Some(inputs) => inputs,
+                None => {
+                    let mut inputs = report.corpus[rng.below(report.corpus.len())].clone();
+                    for _ in 0..=rng.below(3) {
+                        self.mutate(&mut rng, &dictionary, &mut inputs);
+                    }
+                    inputs
+                }
+            };
+            let execution = self.execute(&prog, &inputs);
+            report.runs += 1;
+            inputs.truncate(execution.consumed);
+            if report.coverage.merge(&execution.coverage) || report.corpus.is_empty() {
+                report.corpus.push(inputs.clone());
+            }
+            if execution.reached_target && !report.findings.iter().any(is_target) {
+                report.findings.push(Finding {
+                    inputs: inputs.clone(),
+                    kind: FindingKind::Target,
+                });
+            }
+            if let Some((error, pc)) = execution.error {
+                if errors.insert((mem::discriminant(&error), pc)) {
+                    report.findings.push(Finding {
+                        inputs,
+                        kind: FindingKind::Error(error),
+                    });
+                }
+            }
+        }
+        report
+    }
+}
+
+/// The cell a position or relative mode parameter points to.
+fn address(machine: &Intcode, param: Param) -> Option<usize> {
+    let address = match param.mode {
+        Mode::Position => param.value,
+        Mode::Relative => machine.relative_base().wrapping_add(param.value),
+        Mode::Immediate => return None,
+    };
+    usize::try_from(address).ok()
+}
+
+/// A parameter's value, without firing watchpoints. Cells past the end of
+/// memory read as 0.
+fn load(machine: &Intcode, param: Param) -> i64 {
+    match param.mode {
+        Mode::Immediate => param.value,
+        _ => address(machine, param)
+            .and_then(|address| machine.memory().get(address))
+            .unwrap_or(0),
+    }
+}
+
+fn is_target(finding: &Finding) -> bool {
+    matches!(finding.kind, FindingKind::Target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_target_and_crash() {
+        // Reads x, and if it's 42 reads y, crashing at 22 if y < 0 and otherwise
+        // outputting 1 at 18.
+        let mut prog = vec![
+            3, 30, 1008, 30, 42, 31, 1006, 31, 20, 3, 32, 1007, 32, 0, 31, 1005, 31, 22, 104, 1,
+            99, 0, 42,
+        ];
+        prog.resize(33, 0);
+        let report = Fuzzer::new().runs(2000).target(18).run(&Intcode::new(prog));
+        assert!(report.coverage.branches.contains(&(6, false)));
+        assert!(report.coverage.branches.contains(&(6, true)));
+        assert!(report.coverage.branches.contains(&(11, true)));
+
+        let target = report.findings.iter().find(|f| is_target(f)).unwrap();
+        assert_eq!(target.inputs[0], 42);
+        assert!(target.inputs[1] >= 0);
+        let crash = report
+            .findings
+            .iter()
+            .find(|f| {
+                matches!(
+                    f.kind,
+                    FindingKind::Error(Error::UnknownOpcode { pc: 22, .. })
+                )
+            })
+            .unwrap();
+        assert_eq!(crash.inputs[0], 42);
+        assert!(crash.inputs[1] < 0);
+    }
+
+    #[test]
+    fn large_inputs() {
+        // Reads x and y and outputs x * y + x, which overflows for most inputs.
+        let prog = Intcode::new(vec![
+            3, 15, 3, 16, 2, 15, 16, 17, 1, 17, 15, 17, 4, 17, 99, 0, 0, 0,
+        ]);
+        let report = Fuzzer::new().runs(500).run(&prog);
+        assert!(report.findings.is_empty());
+    }
+
+    #[test]
+    fn branch_directions() {
+        // Jumps to 3, just past the jump, then checks whether rb[1] is 2, writing
+        // the result to rb[0].
+        let prog = Intcode::new(vec![1105, 1, 3, 109, 7, 21208, 1, 2, 0, 99]);
+        let report = Fuzzer::new().runs(1).run(&prog);
+        assert_eq!(
+            report.coverage.branches,
+            [(0, true), (5, false)].iter().copied().collect()
+        );
+    }
+}
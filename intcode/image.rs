@@ -0,0 +1,125 @@
+use crate::{parse, Result};
+use std::io;
+use std::io::{Read, Write};
+
+/// Starts every image in the compact binary format.
+pub const BINARY_MAGIC: &[u8; 4] = b"ICB1";
+
+/// Writes values in the canonical comma separated format, with a trailing newline.
+pub fn write_text<W: Write>(values: &[i64], mut out: W) -> io::Result<()> {
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        write!(out, "{}", value)?;
+    }
+    out.write_all(b"\n")
+}
+
+pub fn to_text(values: &[i64]) -> String {
+    let mut out = Vec::new();
+    write_text(values, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn write_varint<W: Write>(mut value: u64, out: &mut W) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return out.write_all(&[byte]);
+        }
+        out.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        input.read_exact(&mut byte)?;
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "varint too long",
+    ))
+}
+
+/// Writes values in the binary format: the magic, then the number of values and
+/// each zigzag encoded value as LEB128 varints. Small values take a byte each.
+pub fn write_binary<W: Write>(values: &[i64], mut out: W) -> io::Result<()> {
+    out.write_all(BINARY_MAGIC)?;
+    write_varint(values.len() as u64, &mut out)?;
+    for &value in values {
+        write_varint(((value << 1) ^ (value >> 63)) as u64, &mut out)?;
+    }
+    Ok(())
+}
+
+pub fn read_binary<R: Read>(mut input: R) -> Result<Vec<i64>> {
+    let mut magic = [0; 4];
+    input.read_exact(&mut magic)?;
+    if &magic != BINARY_MAGIC {
+        return Err(
+            io::Error::new(io::ErrorKind::InvalidData, "not a binary intcode image").into(),
+        );
+    }
+    let len = read_varint(&mut input)?;
+    let mut values = Vec::new();
+    for _ in 0..len {
+        let zigzag = read_varint(&mut input)?;
+        values.push((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64));
+    }
+    Ok(values)
+}
+
+/// Reads an image in either format, telling them apart by the binary magic.
+pub fn read_image<R: Read>(mut input: R) -> Result<Vec<i64>> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    if data.starts_with(BINARY_MAGIC) {
+        read_binary(data.as_slice())
+    } else {
+        let text =
+            String::from_utf8(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(parse::parse_program(&text)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: &[i64] = &[
+        1,
+        0,
+        -1,
+        63,
+        -64,
+        64,
+        1125899906842624,
+        i64::MAX,
+        i64::MIN,
+        99,
+    ];
+
+    #[test]
+    fn text_round_trip() {
+        let text = to_text(VALUES);
+        assert!(text.starts_with("1,0,-1,63,-64,64,1125899906842624,"));
+        assert_eq!(read_image(text.as_bytes()).unwrap(), VALUES);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let mut data = Vec::new();
+        write_binary(VALUES, &mut data).unwrap();
+        assert_eq!(&data[..7], b"ICB1\x0a\x02\x00");
+        assert_eq!(read_image(data.as_slice()).unwrap(), VALUES);
+        assert!(read_binary(&data[..data.len() - 1]).is_err());
+    }
+}
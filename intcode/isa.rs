@@ -0,0 +1,467 @@
+use crate::{Intcode, Result};
+use std::fmt;
+use std::ops::BitOr;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl Mode {
+    /// The mode for a parameter mode digit.
+    pub fn from_digit(digit: i64) -> Option<Mode> {
+        match digit {
+            0 => Some(Mode::Position),
+            1 => Some(Mode::Immediate),
+            2 => Some(Mode::Relative),
+            _ => None,
+        }
+    }
+}
+
+/// A set of parameter modes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Modes(u8);
+
+impl Modes {
+    pub const NONE: Modes = Modes(0);
+    pub const POSITION: Modes = Modes(1);
+    pub const IMMEDIATE: Modes = Modes(2);
+    pub const RELATIVE: Modes = Modes(4);
+    pub const ALL: Modes = Modes(7);
+
+    pub fn contains(self, mode: Mode) -> bool {
+        let bit = match mode {
+            Mode::Position => Modes::POSITION,
+            Mode::Immediate => Modes::IMMEDIATE,
+            Mode::Relative => Modes::RELATIVE,
+        };
+        self.0 & bit.0 != 0
+    }
+}
+
+impl BitOr for Modes {
+    type Output = Modes;
+
+    fn bitor(self, other: Modes) -> Modes {
+        Modes(self.0 | other.0)
+    }
+}
+
+/// What a custom instruction does after its handler returns.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Control {
+    /// Continues with the instruction after this one's parameters.
+    Next,
+    Jump(usize),
+    Halt,
+    /// Stays on this instruction until input is ready. The handler must not have
+    /// changed anything.
+    WaitForInput,
+}
+
+pub type Handler = Arc<dyn Fn(&mut Context) -> Result<Control> + Send + Sync>;
+
+/// The built-in operations, and custom ones.
+#[derive(Clone)]
+pub enum Op {
+    Add,
+    Multiply,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustRelativeBase,
+    Halt,
+    Custom(Handler),
+}
+
+#[derive(Clone)]
+pub struct Instruction {
+    name: String,
+    arity: usize,
+    modes: Modes,
+    op: Op,
+}
+
+impl Instruction {
+    fn builtin(name: &str, arity: usize, op: Op) -> Self {
+        Instruction {
+            name: name.to_string(),
+            arity,
+            modes: Modes::ALL,
+            op,
+        }
+    }
+
+    /// An instruction with `arity` parameters, each allowed any of `modes`,
+    /// that runs `handler`.
+    pub fn custom<F>(name: &str, arity: usize, modes: Modes, handler: F) -> Self
+    where
+        F: Fn(&mut Context) -> Result<Control> + Send + Sync + 'static,
+    {
+        Instruction {
+            name: name.to_string(),
+            arity,
+            modes,
+            op: Op::Custom(Arc::new(handler)),
+        }
+    }
+
+    /// The same instruction, only allowing parameters in `modes`.
+    pub fn with_modes(mut self, modes: Modes) -> Self {
+        self.modes = modes;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn modes(&self) -> Modes {
+        self.modes
+    }
+
+    pub fn op(&self) -> &Op {
+        &self.op
+    }
+}
+
+impl fmt::Debug for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Instruction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .field("modes", &self.modes)
+            .finish()
+    }
+}
+
+/// Decodes opcodes for a machine.
+pub trait InstructionSet: Send + Sync {
+    /// The instruction for an opcode, which is the last two digits of the first
+    /// cell of an instruction.
+    fn instruction(&self, opcode: i64) -> Option<&Instruction>;
+
+    /// A name for errors about opcodes and modes the set leaves out. Unnamed
+    /// sets report them as unknown opcodes and invalid modes.
+    fn name(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// An instruction set that's a table of opcodes, which can be extended with
+/// custom instructions.
+#[derive(Clone, Debug)]
+pub struct Dialect {
+    name: Option<String>,
+    table: Vec<Option<Instruction>>,
+}
+
+impl Dialect {
+    pub fn empty() -> Self {
+        Dialect {
+            name: None,
+            table: vec![None; 100],
+        }
+    }
+
+    /// Names the dialect, so its errors say what it leaves out.
+    pub fn set_name(&mut self, name: &str) -> &mut Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Every instruction from 2019, in every mode.
+    pub fn full() -> Self {
+        let mut dialect = Dialect::empty();
+        dialect
+            .register(1, Instruction::builtin("add", 3, Op::Add))
+            .register(2, Instruction::builtin("mul", 3, Op::Multiply))
+            .register(3, Instruction::builtin("in", 1, Op::Input))
+            .register(4, Instruction::builtin("out", 1, Op::Output))
+            .register(5, Instruction::builtin("jnz", 2, Op::JumpIfTrue))
+            .register(6, Instruction::builtin("jz", 2, Op::JumpIfFalse))
+            .register(7, Instruction::builtin("lt", 3, Op::LessThan))
+            .register(8, Instruction::builtin("eq", 3, Op::Equals))
+            .register(9, Instruction::builtin("arb", 1, Op::AdjustRelativeBase))
+            .register(99, Instruction::builtin("halt", 0, Op::Halt));
+        dialect
+    }
+
+    /// Adds or replaces the instruction for an opcode from 0 to 99.
+    pub fn register(&mut self, opcode: i64, instruction: Instruction) -> &mut Self {
+        assert!((0..100).contains(&opcode), "opcode {} out of range", opcode);
+        self.table[opcode as usize] = Some(instruction);
+        self
+    }
+
+    pub fn remove(&mut self, opcode: i64) -> Option<Instruction> {
+        self.table.get_mut(opcode as usize)?.take()
+    }
+
+    /// Only allows parameters in `modes` for every instruction.
+    pub fn restrict_modes(&mut self, modes: Modes) -> &mut Self {
+        for instruction in self.table.iter_mut().flatten() {
+            instruction.modes = Modes(instruction.modes.0 & modes.0);
+        }
+        self
+    }
+}
+
+impl InstructionSet for Dialect {
+    fn instruction(&self, opcode: i64) -> Option<&Instruction> {
+        if opcode < 0 {
+            return None;
+        }
+        self.table.get(opcode as usize)?.as_ref()
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+/// The instruction sets of each 2019 puzzle that extended Intcode.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Profile {
+    /// Opcodes 1, 2 and 99, in position mode.
+    Day2,
+    /// Opcodes 1 to 8 and 99, in position and immediate mode.
+    Day5,
+    /// Every opcode and mode, from day 9 on.
+    Full,
+}
+
+impl Profile {
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Day2 => "day 2",
+            Profile::Day5 => "day 5",
+            Profile::Full => "full",
+        }
+    }
+
+    /// The profile as a dialect, which can be extended.
+    pub fn dialect(self) -> Dialect {
+        let mut dialect = Dialect::full();
+        dialect.set_name(self.name());
+        match self {
+            Profile::Day2 => {
+                for opcode in 3..=9 {
+                    dialect.remove(opcode);
+                }
+                dialect.restrict_modes(Modes::POSITION);
+            }
+            Profile::Day5 => {
+                dialect.remove(9);
+                dialect.restrict_modes(Modes::POSITION | Modes::IMMEDIATE);
+            }
+            Profile::Full => {}
+        }
+        dialect
+    }
+
+    fn table(self) -> &'static Dialect {
+        static TABLES: [OnceLock<Dialect>; 3] = [OnceLock::new(), OnceLock::new(), OnceLock::new()];
+        TABLES[self as usize].get_or_init(|| self.dialect())
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s {
+            "day2" => Ok(Profile::Day2),
+            "day5" => Ok(Profile::Day5),
+            "full" => Ok(Profile::Full),
+            _ => Err(format!(
+                "unknown profile {:?}, expected day2, day5 or full",
+                s
+            )),
+        }
+    }
+}
+
+impl InstructionSet for Profile {
+    fn instruction(&self, opcode: i64) -> Option<&Instruction> {
+        self.table().instruction(opcode)
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(Profile::name(*self))
+    }
+}
+
+/// The instruction set machines start with, shared between them.
+pub(crate) fn default_set() -> Arc<dyn InstructionSet> {
+    static FULL: OnceLock<Arc<Dialect>> = OnceLock::new();
+    FULL.get_or_init(|| Arc::new(Dialect::full())).clone()
+}
+
+/// What a custom instruction's handler can see and do.
+pub struct Context<'a> {
+    pub(crate) machine: &'a mut Intcode,
+    pub(crate) pc: usize,
+    pub(crate) full_opcode: i64,
+    pub(crate) modes: Modes,
+    pub(crate) input: &'a mut dyn FnMut() -> Option<i64>,
+    pub(crate) output: &'a mut dyn FnMut(i64),
+}
+
+impl<'a> Context<'a> {
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// The mode of parameter `n`, counting from 1.
+    pub fn mode(&self, n: usize) -> Result<Mode> {
+        let isa = &*self.machine.isa;
+        crate::param_mode(isa, self.full_opcode, self.pc, n, self.modes)
+    }
+
+    /// Reads parameter `n` in its mode.
+    pub fn param(&mut self, n: usize) -> Result<i64> {
+        let mode = self.mode(n)?;
+        self.machine.load_param(self.pc, self.pc + n, mode)
+    }
+
+    /// The address parameter `n` points to, for writing to.
+    pub fn address(&self, n: usize) -> Result<usize> {
+        self.machine.store_param(self.pc + n, self.mode(n)?)
+    }
+
+    /// Reads a cell, which counts as a read for watchpoints.
+    pub fn read(&mut self, address: usize) -> Result<i64> {
+        self.machine.load_address(self.pc, address)
+    }
+
+    pub fn write(&mut self, address: usize, value: i64) -> Result<()> {
+        self.machine.store(self.pc, address, value)
+    }
+
+    /// The next input, or `None` if there isn't one yet.
+    pub fn input(&mut self) -> Option<i64> {
+        (self.input)()
+    }
+
+    pub fn output(&mut self, value: i64) {
+        (self.output)(value)
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.machine.relative_base
+    }
+
+    pub fn set_relative_base(&mut self, base: i64) {
+        self.machine.relative_base = base;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, StepResult};
+
+    #[test]
+    fn custom_opcodes() {
+        let mut dialect = Dialect::full();
+        // sqr a, b: writes a * a to b.
+        dialect.register(
+            10,
+            Instruction::custom("sqr", 2, Modes::ALL, |cx| {
+                let x = cx.param(1)?;
+                let address = cx.address(2)?;
+                cx.write(address, x * x)?;
+                Ok(Control::Next)
+            }),
+        );
+        // dbl: outputs twice its input, or halts once there's no more.
+        dialect.register(
+            11,
+            Instruction::custom("dbl", 0, Modes::NONE, |cx| match cx.input() {
+                Some(x) => {
+                    cx.output(2 * x);
+                    Ok(Control::Jump(cx.pc()))
+                }
+                None => Ok(Control::Halt),
+            }),
+        );
+        dialect.register(
+            12,
+            Instruction::custom("pos", 1, Modes::POSITION, |cx| {
+                let x = cx.param(1)?;
+                cx.output(x);
+                Ok(Control::Next)
+            }),
+        );
+        let dialect: Arc<dyn InstructionSet> = Arc::new(dialect);
+
+        let mut prog = Intcode::new(vec![110, 7, 8, 4, 8, 11, 99, 0, 0]);
+        prog.set_instruction_set(dialect.clone());
+        let mut inputs = vec![3, 5].into_iter();
+        let mut outputs = Vec::new();
+        let result = loop {
+            match prog.try_step(&mut || inputs.next(), &mut |x| outputs.push(x)) {
+                Ok(StepResult::Continue) => {}
+                result => break result,
+            }
+        };
+        assert_eq!(result.unwrap(), StepResult::Complete);
+        assert_eq!(outputs, vec![49, 6, 10]);
+
+        let mut prog = Intcode::new(vec![1112, 5, 99]);
+        prog.set_instruction_set(dialect);
+        match prog.run(|| 0, |_| {}) {
+            Err(Error::InvalidParameterMode { index: 1 }) => {}
+            result => panic!("expected invalid mode, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn profiles() {
+        let run = |profile: Profile, prog: Vec<i64>| {
+            let mut prog = Intcode::new(prog);
+            prog.set_instruction_set(Arc::new(profile));
+            prog.run(|| 1, |_| {})
+        };
+        let day2 = vec![1, 0, 0, 0, 2, 0, 0, 0, 99];
+        let day5 = vec![1101, 1, 2, 0, 3, 0, 4, 0, 99];
+        let day9 = vec![109, 1, 204, 0, 99];
+        assert!(run(Profile::Day2, day2.clone()).is_ok());
+        assert!(run(Profile::Day5, day5.clone()).is_ok());
+        assert!(run(Profile::Full, day9.clone()).is_ok());
+        match run(Profile::Day2, day5) {
+            Err(Error::ModeNotInProfile {
+                index: 1,
+                mode: Mode::Immediate,
+                profile,
+            }) => assert_eq!(profile, "day 2"),
+            result => panic!("expected mode error, got {:?}", result),
+        }
+        match run(Profile::Day5, day9) {
+            Err(Error::OpcodeNotInProfile {
+                pc: 0,
+                opcode: 109,
+                profile,
+            }) => assert_eq!(profile, "day 5"),
+            result => panic!("expected opcode error, got {:?}", result),
+        }
+        match run(Profile::Day5, vec![42]) {
+            Err(Error::UnknownOpcode { pc: 0, opcode: 42 }) => {}
+            result => panic!("expected unknown opcode, got {:?}", result),
+        }
+        assert_eq!("day5".parse(), Ok(Profile::Day5));
+    }
+}
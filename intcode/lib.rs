@@ -1,16 +1,94 @@
 use std::convert::TryInto;
 use std::io;
-use std::io::{BufRead, BufReader};
-use std::num;
+use std::ops::RangeBounds;
+use std::sync::Arc;
+
+pub mod amplifier;
+pub mod ascii;
+pub mod compile;
+#[cfg(test)]
+mod conformance;
+pub mod crash;
+pub mod decompile;
+pub mod diff;
+pub mod disasm;
+pub mod explore;
+pub mod fuzz;
+pub mod image;
+pub mod isa;
+pub mod lint;
+pub mod memory;
+pub mod network;
+pub mod parse;
+pub mod program;
+pub mod router;
+pub mod scheduler;
+pub mod search;
+pub mod watch;
+
+pub use crash::MachineError;
+pub use diff::MemoryDiff;
+pub use memory::Memory;
+pub use parse::ParseError;
+pub use program::Program;
+pub use scheduler::MachineStatus;
+pub use watch::{WatchAction, WatchEvent, WatchId, WatchKind};
+
+use isa::{Context, Control, InstructionSet, Mode, Modes, Op};
+use watch::{Access, Watchpoints};
 
 #[derive(Debug)]
 pub enum Error {
     IndexOutOfBounds(usize),
     PcOutOfBounds(i64),
-    UnknownOpcode { pc: usize, opcode: i64 },
+    UnknownOpcode {
+        pc: usize,
+        opcode: i64,
+    },
     ReadIoError(io::Error),
-    ParseIoError(num::ParseIntError),
-    InvalidParameterMode { index: usize },
+    Parse(ParseError),
+    InvalidParameterMode {
+        index: usize,
+    },
+    /// A 2019 opcode that the machine's instruction set leaves out.
+    OpcodeNotInProfile {
+        pc: usize,
+        opcode: i64,
+        profile: String,
+    },
+    /// A parameter mode that the instruction set doesn't allow for an opcode.
+    ModeNotInProfile {
+        index: usize,
+        mode: Mode,
+        profile: String,
+    },
+    /// A position or relative mode parameter pointed below address 0.
+    NegativeAddress {
+        index: usize,
+        address: i64,
+    },
+    StepLimitExceeded {
+        pc: usize,
+        steps: usize,
+    },
+    /// An amplifier circuit was given no phase settings.
+    NoAmplifiers,
+    /// An amplifier search had no phase sequences to try.
+    NoPhaseSequences,
+    /// A machine halted without giving the output it was expected to.
+    NoOutput {
+        machine: String,
+    },
+    /// A search has more sequences than can be numbered.
+    TooManySequences,
+    /// The program asked for more input than it was given.
+    InputExhausted {
+        pc: usize,
+    },
+    /// Every machine that hasn't halted is waiting for input, and none is queued.
+    Deadlock(Vec<MachineStatus>),
+    /// One machine of a multi-machine run failed.
+    Machine(Box<MachineError>),
 }
 
 impl From<io::Error> for Error {
@@ -19,107 +97,176 @@ impl From<io::Error> for Error {
     }
 }
 
-impl From<std::str::Utf8Error> for Error {
-    fn from(_error: std::str::Utf8Error) -> Self {
-        Error::ReadIoError(io::Error::from(io::ErrorKind::InvalidData))
-    }
-}
-
-impl From<num::ParseIntError> for Error {
-    fn from(error: num::ParseIntError) -> Self {
-        Error::ParseIoError(error)
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Self {
+        Error::Parse(error)
     }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Clone, Copy)]
-enum OpcodeParamMode {
-    Position,
-    Immediate,
-}
-
-struct OpcodeParams {
-    value: i64,
-}
-
-#[derive(Clone, Copy)]
-struct Opcode {
-    full: i64,
-}
-
-impl Opcode {
-    fn new(full_opcode: i64) -> Self {
-        Opcode { full: full_opcode }
-    }
-
-    fn opcode(self) -> i64 {
-        self.full % 100
-    }
+/// The most cells memory can grow to. Writing past it fails with
+/// `Error::IndexOutOfBounds`.
+pub const MAX_MEMORY: usize = 1 << 24;
 
-    fn params(self) -> OpcodeParams {
-        OpcodeParams {
-            value: self.full / 100,
-        }
+/// The mode of parameter `n` of the instruction at `pc`, which must be one of
+/// `allowed`.
+fn param_mode(
+    isa: &dyn InstructionSet,
+    full_opcode: i64,
+    pc: usize,
+    n: usize,
+    allowed: Modes,
+) -> Result<Mode> {
+    let index = pc + n;
+    let digit = 10i64
+        .checked_pow(n as u32 + 1)
+        .map_or(0, |scale| full_opcode / scale % 10);
+    match (Mode::from_digit(digit), isa.name()) {
+        (Some(mode), _) if allowed.contains(mode) => Ok(mode),
+        (Some(mode), Some(profile)) => Err(Error::ModeNotInProfile {
+            index,
+            mode,
+            profile: profile.to_string(),
+        }),
+        _ => Err(Error::InvalidParameterMode { index }),
     }
 }
 
-impl Iterator for OpcodeParams {
-    type Item = OpcodeParamMode;
-    fn next(&mut self) -> Option<OpcodeParamMode> {
-        let x = self.value % 10;
-        self.value /= 10;
-        match x {
-            0 => Some(OpcodeParamMode::Position),
-            1 => Some(OpcodeParamMode::Immediate),
-            _ => None,
+/// The error for an opcode the instruction set doesn't have.
+fn missing_opcode(isa: &dyn InstructionSet, pc: usize, opcode: i64) -> Error {
+    match isa.name() {
+        Some(profile) if isa::default_set().instruction(opcode % 100).is_some() => {
+            Error::OpcodeNotInProfile {
+                pc,
+                opcode,
+                profile: profile.to_string(),
+            }
         }
+        _ => Error::UnknownOpcode { pc, opcode },
     }
 }
 
+/// A machine running a `Program`. Machines are cheap to create and clone, since
+/// they share the program and copy memory only as they write to it.
 #[derive(Clone)]
 pub struct Intcode {
     /// The base program, unchanging through multiple runs.
-    prog: Vec<i64>,
+    prog: Program,
 
     /// The copied program, which changes every time the program runs.
-    mem: Vec<i64>,
+    mem: Memory,
+
+    /// Where `step` and `resume` continue from.
+    pc: usize,
+
+    /// What relative mode parameters are relative to.
+    relative_base: i64,
+
+    isa: Arc<dyn InstructionSet>,
+
+    watchpoints: Watchpoints,
 }
 
-#[derive(Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum StepResult {
     Continue,
     Complete,
+    /// A watchpoint asked to pause after this access.
+    Paused(WatchEvent),
+    /// No input was ready, so the pc is still at the input instruction.
+    WaitingForInput,
 }
 
 impl Intcode {
+    /// A machine with its memory loaded from `prog`, so it can `step` or be
+    /// inspected before it first runs.
     pub fn new(prog: Vec<i64>) -> Self {
-        let mut mem = Vec::new();
-        mem.resize_with(prog.len(), Default::default);
-        Self { prog, mem }
+        Self::from_program(Program::new(prog))
+    }
+
+    pub fn from_program(prog: Program) -> Self {
+        Self {
+            mem: prog.initial_memory().clone(),
+            prog,
+            pc: 0,
+            relative_base: 0,
+            isa: isa::default_set(),
+            watchpoints: Default::default(),
+        }
     }
 
     pub fn read<R: io::Read>(input: R) -> Result<Self> {
-        Ok(Self::new(
-            BufReader::new(input)
-                .split(b',')
-                .map(|elem| -> Result<i64> {
-                    Ok(std::str::from_utf8(&elem?)?.trim_end().parse::<i64>()?)
-                })
-                .collect::<Result<Vec<i64>>>()?,
-        ))
+        Ok(Self::from_program(Program::read(input)?))
     }
 
-    pub fn program(&self) -> &[i64] {
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(Self::from_program(Program::parse(text)?))
+    }
+
+    pub fn program(&self) -> &Program {
         &self.prog
     }
 
-    pub fn memory(&self) -> &[i64] {
+    pub fn memory(&self) -> &Memory {
         &self.mem
     }
 
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    pub fn instruction_set(&self) -> &Arc<dyn InstructionSet> {
+        &self.isa
+    }
+
+    /// Decodes instructions with `isa` rather than the full 2019 instruction set.
+    pub fn set_instruction_set(&mut self, isa: Arc<dyn InstructionSet>) {
+        self.isa = isa;
+    }
+
+    /// Calls `callback` whenever an instruction reads or writes a cell in `addresses`,
+    /// as chosen by `kind`. The callback can pause execution after the instruction.
+    pub fn watch<R, F>(&mut self, addresses: R, kind: WatchKind, callback: F) -> WatchId
+    where
+        R: RangeBounds<usize>,
+        F: FnMut(&WatchEvent) -> WatchAction + Send + 'static,
+    {
+        self.watchpoints.add(addresses, kind, callback)
+    }
+
+    pub fn unwatch(&mut self, id: WatchId) -> bool {
+        self.watchpoints.remove(id)
+    }
+
+    /// Which cells the last run changed, relative to the program.
+    pub fn diff(&self) -> MemoryDiff {
+        MemoryDiff::between(&self.prog.image(), &self.mem.to_vec())
+    }
+
+    /// Writes the program in the canonical comma separated format.
+    pub fn write_program<W: io::Write>(&self, out: W) -> io::Result<()> {
+        image::write_text(&self.prog.image(), out)
+    }
+
+    /// Writes the memory as left by the last run, in the same format as `write_program`.
+    pub fn write_memory<W: io::Write>(&self, out: W) -> io::Result<()> {
+        image::write_text(&self.mem.to_vec(), out)
+    }
+
     pub fn reset_memory(&mut self) {
-        self.mem.copy_from_slice(&self.prog);
+        self.mem = self.prog.initial_memory().clone();
+    }
+
+    /// Gets ready to `step` or `resume` from the start of the program.
+    pub fn reset(&mut self) {
+        self.reset_memory();
+        self.pc = 0;
+        self.relative_base = 0;
     }
 
     pub fn run_instruction<In: FnMut() -> i64, Out: FnMut(i64)>(
@@ -128,105 +275,311 @@ impl Intcode {
         input: &mut In,
         output: &mut Out,
     ) -> Result<StepResult> {
-        let opcode = Opcode::new(
-            *self
-                .mem
-                .get(*pc)
-                .ok_or(Error::PcOutOfBounds((*pc).try_into().unwrap()))?,
+        self.try_run_instruction(pc, &mut || Some(input()), output)
+    }
+
+    /// Like `run_instruction`, but `input` may return `None` when nothing is ready yet.
+    pub fn try_run_instruction<In: FnMut() -> Option<i64>, Out: FnMut(i64)>(
+        &mut self,
+        pc: &mut usize,
+        input: &mut In,
+        output: &mut Out,
+    ) -> Result<StepResult> {
+        let result = self.execute_instruction(pc, input, output);
+        // A pause only applies to the instruction that asked for it. One that
+        // waits for input runs again, and fires its watchpoints again, once
+        // input is ready.
+        let pause = self.watchpoints.take_pause();
+        match (result?, pause) {
+            (StepResult::Continue, Some(event)) => Ok(StepResult::Paused(event)),
+            (result, _) => Ok(result),
+        }
+    }
+
+    fn execute_instruction<In: FnMut() -> Option<i64>, Out: FnMut(i64)>(
+        &mut self,
+        pc: &mut usize,
+        input: &mut In,
+        output: &mut Out,
+    ) -> Result<StepResult> {
+        let full_opcode = self
+            .mem
+            .get(*pc)
+            .ok_or(Error::PcOutOfBounds((*pc).try_into().unwrap()))?;
+        let instruction = self
+            .isa
+            .instruction(full_opcode % 100)
+            .ok_or_else(|| missing_opcode(&*self.isa, *pc, full_opcode))?;
+        // Copied out so the instruction set isn't borrowed while the
+        // instruction runs. Only a custom op takes a reference count.
+        let (op, arity, modes) = (
+            instruction.op().clone(),
+            instruction.arity(),
+            instruction.modes(),
         );
-        match opcode.opcode() {
-            1 | 2 | 7 | 8 => {
-                let mut params = opcode.params();
-                let x = self.load_param(*pc + 1, params.next().unwrap())?;
-                let y = self.load_param(*pc + 2, params.next().unwrap())?;
-                let out_index = self.store_param(*pc + 3, params.next().unwrap())?;
-                let result = match opcode.opcode() {
-                    1 => x + y,
-                    2 => x * y,
-                    7 => (x < y) as i64,
-                    8 => (x == y) as i64,
-                    _ => unreachable!(),
+        let at = *pc;
+        let mode = |machine: &Self, n| param_mode(&*machine.isa, full_opcode, at, n, modes);
+        match op {
+            Op::Add | Op::Multiply | Op::LessThan | Op::Equals => {
+                let x = self.load_param(at, at + 1, mode(self, 1)?)?;
+                let y = self.load_param(at, at + 2, mode(self, 2)?)?;
+                let out_index = self.store_param(at + 3, mode(self, 3)?)?;
+                let result = match op {
+                    Op::Add => x.wrapping_add(y),
+                    Op::Multiply => x.wrapping_mul(y),
+                    Op::LessThan => (x < y) as i64,
+                    _ => (x == y) as i64,
                 };
-                self.set_item(out_index, result)?;
+                self.store(at, out_index, result)?;
                 *pc += 4;
-                Ok(StepResult::Continue)
             }
-            3 => {
-                let out_index = self.store_param(*pc + 1, opcode.params().next().unwrap())?;
-                self.set_item(out_index, input())?;
+            Op::Input => {
+                let out_index = self.store_param(at + 1, mode(self, 1)?)?;
+                let value = match input() {
+                    Some(value) => value,
+                    None => return Ok(StepResult::WaitingForInput),
+                };
+                self.store(at, out_index, value)?;
                 *pc += 2;
-                Ok(StepResult::Continue)
             }
-            4 => {
-                let value = self.load_param(*pc + 1, opcode.params().next().unwrap())?;
+            Op::Output => {
+                let value = self.load_param(at, at + 1, mode(self, 1)?)?;
                 output(value);
                 *pc += 2;
-                Ok(StepResult::Continue)
             }
-            5 | 6 => {
-                let mut params = opcode.params();
-                let value = self.load_param(*pc + 1, params.next().unwrap())?;
-                let new_pc = self
-                    .load_param(*pc + 2, params.next().unwrap())?
-                    .try_into()
-                    .unwrap();
-                let jump = match opcode.opcode() {
-                    5 => value != 0,
-                    6 => value == 0,
-                    _ => unreachable!(),
-                };
-                if jump {
-                    *pc = new_pc;
+            Op::JumpIfTrue | Op::JumpIfFalse => {
+                let value = self.load_param(at, at + 1, mode(self, 1)?)?;
+                let target = self.load_param(at, at + 2, mode(self, 2)?)?;
+                if (value != 0) == matches!(op, Op::JumpIfTrue) {
+                    *pc = target.try_into().or(Err(Error::PcOutOfBounds(target)))?;
                 } else {
                     *pc += 3;
                 }
-                Ok(StepResult::Continue)
             }
-            99 => Ok(StepResult::Complete),
-            _ => Err(Error::UnknownOpcode {
-                pc: *pc,
-                opcode: opcode.full,
-            }),
+            Op::AdjustRelativeBase => {
+                let offset = self.load_param(at, at + 1, mode(self, 1)?)?;
+                self.relative_base = self.relative_base.wrapping_add(offset);
+                *pc += 2;
+            }
+            Op::Halt => return Ok(StepResult::Complete),
+            Op::Custom(handler) => {
+                let mut context = Context {
+                    machine: self,
+                    pc: at,
+                    full_opcode,
+                    modes,
+                    input,
+                    output,
+                };
+                match handler(&mut context)? {
+                    Control::Next => *pc += 1 + arity,
+                    Control::Jump(target) => *pc = target,
+                    Control::Halt => return Ok(StepResult::Complete),
+                    Control::WaitForInput => return Ok(StepResult::WaitingForInput),
+                }
+            }
+        }
+        Ok(StepResult::Continue)
+    }
+
+    /// Executes the instruction at the current pc.
+    pub fn step<In: FnMut() -> i64, Out: FnMut(i64)>(
+        &mut self,
+        input: &mut In,
+        output: &mut Out,
+    ) -> Result<StepResult> {
+        self.try_step(&mut || Some(input()), output)
+    }
+
+    /// Like `step`, but `input` may return `None` when nothing is ready yet.
+    pub fn try_step<In: FnMut() -> Option<i64>, Out: FnMut(i64)>(
+        &mut self,
+        input: &mut In,
+        output: &mut Out,
+    ) -> Result<StepResult> {
+        let mut pc = self.pc;
+        let result = self.try_run_instruction(&mut pc, input, output);
+        self.pc = pc;
+        result
+    }
+
+    /// Runs from the current pc until the program completes or a watchpoint pauses it.
+    pub fn resume<In: FnMut() -> i64, Out: FnMut(i64)>(
+        &mut self,
+        mut input: In,
+        mut output: Out,
+    ) -> Result<StepResult> {
+        loop {
+            match self.step(&mut input, &mut output)? {
+                StepResult::Continue => {}
+                result => return Ok(result),
+            }
         }
     }
 
+    /// Runs the program from the start, returning `StepResult::Complete` or
+    /// `StepResult::Paused`, after which `resume` continues the run.
     pub fn run<In: FnMut() -> i64, Out: FnMut(i64)>(
+        &mut self,
+        input: In,
+        output: Out,
+    ) -> Result<StepResult> {
+        self.reset();
+        self.resume(input, output)
+    }
+
+    /// Like `run`, but gives up after executing `max_steps` instructions.
+    pub fn run_limited<In: FnMut() -> i64, Out: FnMut(i64)>(
         &mut self,
         mut input: In,
         mut output: Out,
-    ) -> Result<()> {
-        self.reset_memory();
-        let pc = &mut 0;
-        while self.run_instruction(pc, &mut input, &mut output)? != StepResult::Complete {}
-        Ok(())
+        max_steps: usize,
+    ) -> Result<StepResult> {
+        self.reset();
+        for _ in 0..max_steps {
+            match self.step(&mut input, &mut output)? {
+                StepResult::Continue => {}
+                result => return Ok(result),
+            }
+        }
+        Err(Error::StepLimitExceeded {
+            pc: self.pc,
+            steps: max_steps,
+        })
     }
 
+    /// Reads a cell. Cells past the end of memory read as 0.
     fn get_item(&self, index: usize) -> Result<i64> {
+        Ok(self.mem.get(index).unwrap_or(0))
+    }
+
+    /// Writes a cell, growing memory if it's past the end.
+    fn set_item(&mut self, index: usize, value: i64) -> Result<()> {
+        if index >= MAX_MEMORY {
+            return Err(Error::IndexOutOfBounds(index));
+        }
+        self.mem.grow(index + 1);
         self.mem
-            .get(index)
-            .copied()
+            .set(index, value)
             .ok_or(Error::IndexOutOfBounds(index))
     }
 
-    fn set_item(&mut self, index: usize, value: i64) -> Result<()> {
-        *self
-            .mem
-            .get_mut(index)
-            .ok_or(Error::IndexOutOfBounds(index))? = value;
+    /// Writes a cell on behalf of the instruction at `pc`.
+    fn store(&mut self, pc: usize, address: usize, value: i64) -> Result<()> {
+        let old = self.get_item(address)?;
+        self.set_item(address, value)?;
+        self.watchpoints.fire(WatchEvent {
+            pc,
+            address,
+            access: Access::Write,
+            old,
+            new: value,
+        });
         Ok(())
     }
 
-    fn load_param(&self, index: usize, mode: OpcodeParamMode) -> Result<i64> {
+    /// Reads a cell on behalf of the instruction at `pc`.
+    fn load_address(&mut self, pc: usize, address: usize) -> Result<i64> {
+        let value = self.get_item(address)?;
+        self.watchpoints.fire(WatchEvent {
+            pc,
+            address,
+            access: Access::Read,
+            old: value,
+            new: value,
+        });
+        Ok(value)
+    }
+
+    fn load_param(&mut self, pc: usize, index: usize, mode: Mode) -> Result<i64> {
         match mode {
-            OpcodeParamMode::Position => self.get_item(self.get_item(index)?.try_into().unwrap()),
-            OpcodeParamMode::Immediate => self.get_item(index),
+            Mode::Position | Mode::Relative => {
+                let address = self.address_param(index, mode)?;
+                self.load_address(pc, address)
+            }
+            Mode::Immediate => self.get_item(index),
         }
     }
 
-    fn store_param(&self, index: usize, mode: OpcodeParamMode) -> Result<usize> {
+    /// Reads the address a position or relative mode parameter points to.
+    fn address_param(&self, index: usize, mode: Mode) -> Result<usize> {
+        let address = match mode {
+            Mode::Relative => self.relative_base.wrapping_add(self.get_item(index)?),
+            _ => self.get_item(index)?,
+        };
+        address
+            .try_into()
+            .or(Err(Error::NegativeAddress { index, address }))
+    }
+
+    fn store_param(&self, index: usize, mode: Mode) -> Result<usize> {
         match mode {
-            OpcodeParamMode::Position => Ok(self.get_item(index)?.try_into().unwrap()),
-            OpcodeParamMode::Immediate => Err(Error::InvalidParameterMode { index }),
+            Mode::Position | Mode::Relative => self.address_param(index, mode),
+            Mode::Immediate => Err(Error::InvalidParameterMode { index }),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_to_error(program: Vec<i64>) -> Error {
+        Intcode::new(program).run(|| 0, |_| {}).unwrap_err()
+    }
+
+    #[test]
+    fn bad_parameters() {
+        assert!(matches!(
+            run_to_error(vec![301, 0, 0, 0, 99]),
+            Error::InvalidParameterMode { index: 1 }
+        ));
+        // Writes in immediate mode.
+        assert!(matches!(
+            run_to_error(vec![11101, 0, 0, 0, 99]),
+            Error::InvalidParameterMode { index: 3 }
+        ));
+        assert!(matches!(
+            run_to_error(vec![1, -1, 0, 0, 99]),
+            Error::NegativeAddress {
+                index: 1,
+                address: -1
+            }
+        ));
+        assert!(matches!(
+            run_to_error(vec![1105, 1, -4]),
+            Error::PcOutOfBounds(-4)
+        ));
+    }
+
+    #[test]
+    fn memory_past_the_image() {
+        // Reads 100 as 0 and writes past the end, which grows memory.
+        let prog = &mut Intcode::new(vec![1001, 100, 7, 50, 99]);
+        prog.run(|| 0, |_| {}).unwrap();
+        assert_eq!(prog.memory().len(), 51);
+        assert_eq!(prog.memory()[50], 7);
+        // Memory only grows so far.
+        let at = MAX_MEMORY as i64;
+        assert!(matches!(
+            run_to_error(vec![1101, 0, 0, at, 99]),
+            Error::IndexOutOfBounds(index) if index == MAX_MEMORY
+        ));
+    }
+
+    #[test]
+    fn wrapping_arithmetic() {
+        // Outputs i64::MAX + 1 and i64::MAX * 2.
+        #[rustfmt::skip]
+        let program = vec![
+            1101, i64::MAX, 1, 13,
+            1102, i64::MAX, 2, 14,
+            4, 13, 4, 14, 99, 0, 0,
+        ];
+        let mut outputs = Vec::new();
+        Intcode::new(program)
+            .run(|| 0, |out| outputs.push(out))
+            .unwrap();
+        assert_eq!(outputs, [i64::MIN, -2]);
+    }
+}
@@ -0,0 +1,301 @@
+//! Finds problems in a program without running it.
+//!
+//! Code is found by tracing from address 0. If the program jumps to computed
+//! addresses, the address after an unconditional jump is traced too when the
+//! program uses it as an immediate value, since that's how a call passes its
+//! return address. Unreachable cells are only reported when that finds every
+//! jump target and the trace never stops at code the program patches, since
+//! otherwise the program may well reach them.
+
+use crate::disasm::{self, Param};
+use crate::isa::{InstructionSet, Mode};
+use crate::Memory;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    /// Running into it fails.
+    Error,
+    /// Likely a mistake, though it may work.
+    Warning,
+    Info,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Problem {
+    /// An opcode the instruction set doesn't have, on a reachable path.
+    UnknownOpcode(i64),
+    /// A mode digit that isn't a mode, or is one the instruction doesn't allow.
+    InvalidMode { param: usize, digit: i64 },
+    /// A position-mode parameter that's negative or past the end of the image.
+    OutsideImage { param: usize, address: i64 },
+    /// A parameter that's written to in immediate mode.
+    ImmediateWrite { param: usize },
+    /// Cells up to `end` that are never reached or referred to by position.
+    Unreachable { end: usize },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Finding {
+    pub address: usize,
+    pub severity: Severity,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: ", self.address, self.severity)?;
+        match self.problem {
+            Problem::UnknownOpcode(opcode) => write!(f, "unknown opcode {}", opcode),
+            Problem::InvalidMode { param, digit } => {
+                write!(f, "parameter {} has invalid mode {}", param, digit)
+            }
+            Problem::OutsideImage { param, address } if address < 0 => {
+                write!(
+                    f,
+                    "parameter {} refers to negative address {}",
+                    param, address
+                )
+            }
+            Problem::OutsideImage { param, address } => write!(
+                f,
+                "parameter {} refers to {}, past the end of the image",
+                param, address
+            ),
+            Problem::ImmediateWrite { param } => {
+                write!(f, "parameter {} is written in immediate mode", param)
+            }
+            Problem::Unreachable { end } => write!(
+                f,
+                "cells {}..{} are never reached or referred to",
+                self.address, end
+            ),
+        }
+    }
+}
+
+/// Why the instruction at `address` doesn't decode, and how many cells it
+/// takes up.
+fn diagnose(isa: &dyn InstructionSet, memory: &Memory, address: usize) -> (Problem, usize) {
+    // Past the end of the image, the opcode reads as 0.
+    let opcode = memory.get(address).unwrap_or(0);
+    let instruction = match isa.instruction(opcode % 100) {
+        Some(instruction) => instruction,
+        None => return (Problem::UnknownOpcode(opcode), 1),
+    };
+    (1..=instruction.arity())
+        .map(|param| (param, disasm::mode_digit(opcode, param)))
+        .find(|&(_, digit)| {
+            !Mode::from_digit(digit).is_some_and(|mode| instruction.modes().contains(mode))
+        })
+        .map(|(param, digit)| {
+            (
+                Problem::InvalidMode { param, digit },
+                1 + instruction.arity(),
+            )
+        })
+        .unwrap_or((Problem::UnknownOpcode(opcode), 1))
+}
+
+/// Marks the cells in `range` that are in the image.
+fn mark(used: &mut [bool], range: Range<usize>) {
+    for cell in used.iter_mut().take(range.end).skip(range.start) {
+        *cell = true;
+    }
+}
+
+/// Checks `memory` for problems that show without running it, in address
+/// order.
+pub fn lint(isa: &dyn InstructionSet, memory: &Memory) -> Vec<Finding> {
+    let trace = disasm::trace_program(isa, memory);
+    let mut findings = Vec::new();
+    let mut used = vec![false; memory.len()];
+    let mut written = BTreeSet::new();
+    for decoded in trace.instructions.values() {
+        mark(&mut used, decoded.address..decoded.next());
+        for (i, param) in decoded.params.iter().enumerate() {
+            if param.mode != Mode::Position {
+                continue;
+            }
+            match usize::try_from(param.value) {
+                Ok(address) if address < memory.len() => used[address] = true,
+                _ => findings.push(Finding {
+                    address: decoded.address,
+                    severity: if param.value < 0 {
+                        Severity::Error
+                    } else {
+                        Severity::Warning
+                    },
+                    problem: Problem::OutsideImage {
+                        param: i + 1,
+                        address: param.value,
+                    },
+                }),
+            }
+        }
+        match decoded.written() {
+            Some(Param {
+                mode: Mode::Immediate,
+                ..
+            }) => findings.push(Finding {
+                address: decoded.address,
+                severity: Severity::Error,
+                // The written parameter is always the last.
+                problem: Problem::ImmediateWrite {
+                    param: decoded.params.len(),
+                },
+            }),
+            Some(Param {
+                mode: Mode::Position,
+                value,
+            }) if value >= 0 => {
+                written.insert(value as usize);
+            }
+            _ => {}
+        }
+    }
+    for &address in trace.invalid.iter() {
+        let (problem, len) = diagnose(isa, memory, address);
+        mark(&mut used, address..address + len);
+        findings.push(Finding {
+            address,
+            // The program may fix up its own code before it gets there.
+            severity: if written.contains(&address) {
+                Severity::Warning
+            } else {
+                Severity::Error
+            },
+            problem,
+        });
+    }
+    let complete =
+        trace.computed_jumps.is_empty() && !trace.invalid.iter().any(|a| written.contains(a));
+    let mut address = 0;
+    while complete && address < memory.len() {
+        let end = (address..memory.len())
+            .find(|&cell| used[cell] != used[address])
+            .unwrap_or(memory.len());
+        if !used[address] {
+            findings.push(Finding {
+                address,
+                severity: Severity::Info,
+                problem: Problem::Unreachable { end },
+            });
+        }
+        address = end;
+    }
+    findings.sort_by_key(|finding| (finding.address, finding.severity));
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::compile;
+    use crate::isa::Profile;
+    use crate::Intcode;
+
+    fn linted_file(text: &str) -> Vec<String> {
+        let prog = Intcode::parse(text).unwrap();
+        lint(&Profile::Full, prog.memory())
+            .iter()
+            .map(|finding| finding.to_string())
+            .collect()
+    }
+
+    fn linted(program: &[i64]) -> Vec<String> {
+        lint(&Profile::Full, &Memory::from_slice(program))
+            .iter()
+            .map(|finding| finding.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn findings() {
+        #[rustfmt::skip]
+        let program = [
+            1, 19, 20, 21,
+            11101, 1, 2, 3,
+            4, -1,
+            1005, 100, 14,
+            42,
+            304, 0,
+            104, 5, 99,
+            7, 0, 0,
+        ];
+        assert_eq!(
+            linted(&program),
+            vec![
+                "4: error: parameter 3 is written in immediate mode",
+                "8: error: parameter 1 refers to negative address -1",
+                "10: warning: parameter 1 refers to 100, past the end of the image",
+                "13: error: unknown opcode 42",
+                "14: error: parameter 1 has invalid mode 3",
+                "16: info: cells 16..19 are never reached or referred to",
+            ]
+        );
+        // Day 5 style code that patches an opcode before running it.
+        assert_eq!(
+            linted(&[1101, 1, 98, 4, 0]),
+            vec!["4: warning: unknown opcode 0"]
+        );
+        // Day 2 and 5 programs can't use relative mode.
+        assert_eq!(
+            lint(&Profile::Day5, &Memory::from_slice(&[204, 0, 99]))
+                .iter()
+                .map(|finding| finding.problem.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Problem::InvalidMode { param: 1, digit: 2 },
+                Problem::Unreachable { end: 3 },
+            ]
+        );
+    }
+
+    #[test]
+    fn compiled_programs() {
+        let source = "
+            let total = 0;
+            fn add(a, b) { return a + b; }
+            fn main() {
+                let i = 0;
+                while (i < 3) {
+                    total = add(total, input());
+                    i = i + 1;
+                }
+                output(total);
+            }
+        ";
+        // Calls are followed through their return addresses, but returns are
+        // computed jumps, so nothing is reported as unreachable.
+        let program = compile(source).unwrap();
+        assert_eq!(linted(&program), Vec::<String>::new());
+    }
+
+    #[test]
+    fn puzzle_inputs() {
+        // Day 5 patches the instruction at 6 with its input, and day 7 jumps
+        // to a computed address, so neither has cells reported as unreachable.
+        assert_eq!(
+            linted_file(include_str!("../05/input.txt")),
+            vec!["6: warning: unknown opcode 1100"]
+        );
+        assert_eq!(
+            linted_file(include_str!("../07/input.txt")),
+            Vec::<String>::new()
+        );
+    }
+}
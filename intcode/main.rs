@@ -0,0 +1,408 @@
+use intcode::ascii::Ascii;
+use intcode::fuzz::{FindingKind, Fuzzer};
+use intcode::isa::Profile;
+use intcode::lint::{self, Severity};
+use intcode::watch::Access;
+use intcode::{compile, decompile, image, Error, Intcode, WatchAction, WatchKind};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::env;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::ops::Range;
+use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
+
+const USAGE: &str = "usage: intcode run <program> [--input <values>]... [--input-file <file>] \
+                     [--ascii] [--profile <day2|day5|full>] [--max-steps <n>] [--dump-memory] [--save-memory <file> [--binary]] \
+                     [--watch <addr>[..<end>][:r|w|rw]]...
+       intcode fuzz <program> [--runs <n>] [--seed <n>] [--target <pc>] [--max-steps <n>] \
+                     [--max-inputs <n>] [--input <values>]...
+       intcode compile <source> [--output <file>]
+       intcode decompile <program> [--profile <day2|day5|full>]
+       intcode lint <program> [--profile <day2|day5|full>]";
+
+#[derive(Default)]
+struct RunOptions {
+    program: String,
+    inputs: Vec<String>,
+    input_file: Option<String>,
+    ascii: bool,
+    profile: Option<Profile>,
+    max_steps: Option<usize>,
+    dump_memory: bool,
+    save_memory: Option<String>,
+    binary: bool,
+    watches: Vec<(Range<usize>, WatchKind)>,
+}
+
+fn fail(msg: impl Display) -> ! {
+    eprintln!("intcode: {}", msg);
+    process::exit(1)
+}
+
+fn option_value<I: Iterator<Item = String>>(args: &mut I, name: &str) -> String {
+    args.next()
+        .unwrap_or_else(|| fail(format!("{} needs a value", name)))
+}
+
+fn number_value<I: Iterator<Item = String>, T: FromStr>(args: &mut I, name: &str) -> T {
+    let value = option_value(args, name);
+    value
+        .parse()
+        .unwrap_or_else(|_| fail(format!("bad {} {:?}", name, value)))
+}
+
+fn parse_run_args<I: Iterator<Item = String>>(mut args: I) -> RunOptions {
+    let mut opts = RunOptions::default();
+    let mut program = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => opts.inputs.push(option_value(&mut args, "--input")),
+            "--input-file" => opts.input_file = Some(option_value(&mut args, "--input-file")),
+            "--ascii" => opts.ascii = true,
+            "--profile" => {
+                let value = option_value(&mut args, "--profile");
+                opts.profile = Some(value.parse().unwrap_or_else(|e| fail(e)));
+            }
+            "--max-steps" => opts.max_steps = Some(number_value(&mut args, "--max-steps")),
+            "--dump-memory" => opts.dump_memory = true,
+            "--save-memory" => opts.save_memory = Some(option_value(&mut args, "--save-memory")),
+            "--binary" => opts.binary = true,
+            "--watch" => {
+                let value = option_value(&mut args, "--watch");
+                opts.watches
+                    .push(parse_watch(&value).unwrap_or_else(|e| fail(e)));
+            }
+            _ if arg.starts_with("--") => fail(format!("unknown option {}\n{}", arg, USAGE)),
+            _ if program.is_none() => program = Some(arg),
+            _ => fail(format!("unexpected argument {}\n{}", arg, USAGE)),
+        }
+    }
+    opts.program = program.unwrap_or_else(|| fail(USAGE));
+    opts
+}
+
+/// Parses `225`, `225..230` or either with a `:r`, `:w` or `:rw` suffix. Watches writes by default.
+fn parse_watch(spec: &str) -> Result<(Range<usize>, WatchKind), String> {
+    let bad = || format!("bad --watch {:?}", spec);
+    let (addresses, kind) = match spec.find(':') {
+        Some(i) => (&spec[..i], &spec[i + 1..]),
+        None => (spec, "w"),
+    };
+    let kind = match kind {
+        "r" => WatchKind::Read,
+        "w" => WatchKind::Write,
+        "rw" => WatchKind::ReadWrite,
+        _ => return Err(bad()),
+    };
+    let parse = |x: &str| x.parse::<usize>().map_err(|_| bad());
+    let range = match addresses.find("..") {
+        Some(i) => parse(&addresses[..i])?..parse(&addresses[i + 2..])?,
+        None => {
+            let address = parse(addresses)?;
+            address..address.checked_add(1).ok_or_else(bad)?
+        }
+    };
+    if range.start >= range.end {
+        return Err(bad());
+    }
+    Ok((range, kind))
+}
+
+/// Parses comma or whitespace separated integers.
+fn parse_values(text: &str) -> Vec<i64> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            x.parse()
+                .unwrap_or_else(|_| fail(format!("bad input value {:?}", x)))
+        })
+        .collect()
+}
+
+fn read_stdin_line() -> String {
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(0) => fail("program wants more input"),
+        Ok(_) => line.trim_end_matches(&['\r', '\n'][..]).to_string(),
+        Err(e) => fail(e),
+    }
+}
+
+fn run_program<In: FnMut() -> i64, Out: FnMut(i64)>(
+    prog: &mut Intcode,
+    opts: &RunOptions,
+    input: In,
+    output: Out,
+) {
+    let result = match opts.max_steps {
+        Some(max_steps) => prog.run_limited(input, output, max_steps),
+        None => prog.run(input, output),
+    };
+    if let Err(e) = result {
+        fail(format!("intcode error, {:?}", e));
+    }
+}
+
+fn load_program(path: &str) -> Intcode {
+    let file = File::open(path).unwrap_or_else(|e| fail(format!("cannot open {}: {}", path, e)));
+    Intcode::new(image::read_image(file).unwrap_or_else(|e| match e {
+        Error::Parse(e) => fail(format!("cannot parse {}: {}", path, e)),
+        e => fail(format!("cannot read {}: {:?}", path, e)),
+    }))
+}
+
+fn run(opts: RunOptions) {
+    let prog = &mut load_program(&opts.program);
+    if let Some(profile) = opts.profile {
+        prog.set_instruction_set(Arc::new(profile));
+    }
+    for (range, kind) in opts.watches.iter() {
+        prog.watch(range.clone(), *kind, |event| {
+            let access = match event.access {
+                Access::Read => "read",
+                Access::Write => "wrote",
+            };
+            eprintln!(
+                "watch: pc {} {} {}: {} -> {}",
+                event.pc, access, event.address, event.old, event.new
+            );
+            WatchAction::Continue
+        });
+    }
+    let input_file = opts.input_file.as_ref().map(|path| {
+        fs::read_to_string(path).unwrap_or_else(|e| fail(format!("cannot read {}: {}", path, e)))
+    });
+
+    if opts.ascii {
+        let ascii = RefCell::new(Ascii::new());
+        for line in opts.inputs.iter() {
+            ascii.borrow_mut().push_line(line);
+        }
+        for line in input_file.iter().flat_map(|text| text.lines()) {
+            ascii.borrow_mut().push_line(line);
+        }
+        run_program(
+            prog,
+            &opts,
+            || loop {
+                if let Some(c) = ascii.borrow_mut().next_input() {
+                    break c;
+                }
+                ascii.borrow_mut().push_line(&read_stdin_line());
+            },
+            |out| {
+                if let Some(line) = ascii.borrow_mut().push_output(out) {
+                    println!("{}", line);
+                }
+            },
+        );
+        let ascii = ascii.into_inner();
+        if !ascii.partial_line().is_empty() {
+            println!("{}", ascii.partial_line());
+        }
+        for value in ascii.finish().values {
+            println!("{}", value);
+        }
+    } else {
+        let mut inputs: VecDeque<i64> = opts
+            .inputs
+            .iter()
+            .chain(input_file.iter())
+            .flat_map(|text| parse_values(text))
+            .collect();
+        run_program(
+            prog,
+            &opts,
+            || loop {
+                if let Some(x) = inputs.pop_front() {
+                    break x;
+                }
+                inputs.extend(parse_values(&read_stdin_line()));
+            },
+            |out| println!("{}", out),
+        );
+    }
+
+    if opts.dump_memory {
+        prog.write_memory(io::stdout().lock())
+            .unwrap_or_else(|e| fail(e));
+    }
+    if let Some(path) = &opts.save_memory {
+        let file =
+            File::create(path).unwrap_or_else(|e| fail(format!("cannot create {}: {}", path, e)));
+        let mut out = BufWriter::new(file);
+        let result = if opts.binary {
+            image::write_binary(&prog.memory().to_vec(), &mut out)
+        } else {
+            prog.write_memory(&mut out)
+        };
+        result
+            .and_then(|()| out.flush())
+            .unwrap_or_else(|e| fail(format!("cannot write {}: {}", path, e)));
+    }
+}
+
+fn fuzz<I: Iterator<Item = String>>(mut args: I) {
+    let mut fuzzer = Fuzzer::new();
+    let mut program = None;
+    while let Some(arg) = args.next() {
+        fuzzer = match arg.as_str() {
+            "--runs" => fuzzer.runs(number_value(&mut args, "--runs")),
+            "--seed" => fuzzer.seed(number_value(&mut args, "--seed")),
+            "--target" => fuzzer.target(number_value(&mut args, "--target")),
+            "--max-steps" => fuzzer.max_steps(number_value(&mut args, "--max-steps")),
+            "--max-inputs" => fuzzer.max_inputs(number_value(&mut args, "--max-inputs")),
+            "--input" => fuzzer.add_seed(&parse_values(&option_value(&mut args, "--input"))),
+            _ if arg.starts_with("--") => fail(format!("unknown option {}\n{}", arg, USAGE)),
+            _ if program.is_none() => {
+                program = Some(arg);
+                fuzzer
+            }
+            _ => fail(format!("unexpected argument {}\n{}", arg, USAGE)),
+        };
+    }
+    let prog = load_program(&program.unwrap_or_else(|| fail(USAGE)));
+    let report = fuzzer.run(&prog);
+    println!(
+        "{} runs covered {} pcs and {} branch directions, with {} inputs in the corpus",
+        report.runs,
+        report.coverage.pcs.len(),
+        report.coverage.branches.len(),
+        report.corpus.len()
+    );
+    for finding in report.findings.iter() {
+        match &finding.kind {
+            FindingKind::Target => println!("reached target with inputs {:?}", finding.inputs),
+            FindingKind::Error(e) => println!("{:?} with inputs {:?}", e, finding.inputs),
+        }
+    }
+}
+
+fn compile<I: Iterator<Item = String>>(mut args: I) {
+    let mut source = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => output = Some(option_value(&mut args, "--output")),
+            _ if arg.starts_with("--") => fail(format!("unknown option {}\n{}", arg, USAGE)),
+            _ if source.is_none() => source = Some(arg),
+            _ => fail(format!("unexpected argument {}\n{}", arg, USAGE)),
+        }
+    }
+    let path = source.unwrap_or_else(|| fail(USAGE));
+    let text =
+        fs::read_to_string(&path).unwrap_or_else(|e| fail(format!("cannot read {}: {}", path, e)));
+    let program = compile::compile(&text).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+    match output {
+        Some(path) => {
+            let file = File::create(&path)
+                .unwrap_or_else(|e| fail(format!("cannot create {}: {}", path, e)));
+            let mut out = BufWriter::new(file);
+            image::write_text(&program, &mut out)
+                .and_then(|()| out.flush())
+                .unwrap_or_else(|e| fail(format!("cannot write {}: {}", path, e)));
+        }
+        None => image::write_text(&program, io::stdout().lock()).unwrap_or_else(|e| fail(e)),
+    }
+}
+
+/// Parses the arguments of a command that takes a program and a profile.
+fn parse_static_args<I: Iterator<Item = String>>(mut args: I) -> (Intcode, Profile) {
+    let mut program = None;
+    let mut profile = Profile::Full;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--profile" => {
+                profile = option_value(&mut args, "--profile")
+                    .parse()
+                    .unwrap_or_else(|e| fail(e))
+            }
+            _ if arg.starts_with("--") => fail(format!("unknown option {}\n{}", arg, USAGE)),
+            _ if program.is_none() => program = Some(arg),
+            _ => fail(format!("unexpected argument {}\n{}", arg, USAGE)),
+        }
+    }
+    (
+        load_program(&program.unwrap_or_else(|| fail(USAGE))),
+        profile,
+    )
+}
+
+fn decompile<I: Iterator<Item = String>>(args: I) {
+    let (prog, profile) = parse_static_args(args);
+    print!("{}", decompile::decompile(&profile, prog.memory()));
+}
+
+fn lint<I: Iterator<Item = String>>(args: I) {
+    let (prog, profile) = parse_static_args(args);
+    let findings = lint::lint(&profile, prog.memory());
+    for finding in findings.iter() {
+        println!("{}", finding);
+    }
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    println!("{} findings, {} errors", findings.len(), errors);
+    if errors > 0 {
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        Some("run") => run(parse_run_args(args)),
+        Some("fuzz") => fuzz(args),
+        Some("compile") => compile(args),
+        Some("decompile") => decompile(args),
+        Some("lint") => lint(args),
+        _ => fail(USAGE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_args() {
+        let args = ["prog.txt", "--input", "1,2", "--watch", "5", "--ascii"];
+        let opts = parse_run_args(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(opts.program, "prog.txt");
+        assert_eq!(opts.inputs, vec!["1,2"]);
+        assert_eq!(opts.watches, vec![(5..6, WatchKind::Write)]);
+        assert!(opts.ascii);
+        assert!(!opts.dump_memory);
+    }
+
+    #[test]
+    fn watch_specs() {
+        assert_eq!(parse_watch("225"), Ok((225..226, WatchKind::Write)));
+        assert_eq!(parse_watch("225..230:r"), Ok((225..230, WatchKind::Read)));
+        assert_eq!(parse_watch("7:rw"), Ok((7..8, WatchKind::ReadWrite)));
+        let max = usize::MAX.to_string();
+        assert_eq!(
+            parse_watch(&format!("0..{}", max)),
+            Ok((0..usize::MAX, WatchKind::Write))
+        );
+        for spec in &[&max[..], "10..5", "10..10", "7:x", "a..b", ""] {
+            assert_eq!(
+                parse_watch(spec),
+                Err(format!("bad --watch {:?}", spec)),
+                "{}",
+                spec
+            );
+        }
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(parse_values("1, 2\n-3"), vec![1, 2, -3]);
+        assert_eq!(parse_values(" "), Vec::<i64>::new());
+    }
+}
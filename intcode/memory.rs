@@ -0,0 +1,154 @@
+use std::hash::{Hash, Hasher};
+use std::ops::Index;
+use std::sync::Arc;
+
+/// Cells in each copy-on-write page.
+pub const PAGE_SIZE: usize = 512;
+
+type Page = [i64; PAGE_SIZE];
+
+/// Machine memory, split into pages shared between clones until written.
+///
+/// Cloning copies nothing: the page table and the pages are shared. The first
+/// write after a clone copies the page table, and each page is copied the first
+/// time it's written.
+#[derive(Clone, Debug, Default)]
+pub struct Memory {
+    pages: Arc<Vec<Arc<Page>>>,
+    len: usize,
+}
+
+impl Memory {
+    pub fn from_slice(values: &[i64]) -> Self {
+        let pages = values
+            .chunks(PAGE_SIZE)
+            .map(|chunk| {
+                let mut page = [0; PAGE_SIZE];
+                page[..chunk.len()].copy_from_slice(chunk);
+                Arc::new(page)
+            })
+            .collect();
+        Memory {
+            pages: Arc::new(pages),
+            len: values.len(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<i64> {
+        if index < self.len {
+            Some(self.pages[index / PAGE_SIZE][index % PAGE_SIZE])
+        } else {
+            None
+        }
+    }
+
+    /// Writes a cell, or returns `None` if it's out of bounds.
+    pub fn set(&mut self, index: usize, value: i64) -> Option<()> {
+        if index >= self.len {
+            return None;
+        }
+        let page = &mut Arc::make_mut(&mut self.pages)[index / PAGE_SIZE];
+        Arc::make_mut(page)[index % PAGE_SIZE] = value;
+        Some(())
+    }
+
+    /// Grows to `len` cells, filling them with zeros. Never shrinks.
+    pub fn grow(&mut self, len: usize) {
+        if len <= self.len {
+            return;
+        }
+        // Cells past the end of the last page are always zero, so new pages can
+        // all share one zero page until they're written.
+        let zero = Arc::new([0; PAGE_SIZE]);
+        let pages = len.div_ceil(PAGE_SIZE);
+        Arc::make_mut(&mut self.pages).resize(pages, zero);
+        self.len = len;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = i64> + '_ {
+        self.pages
+            .iter()
+            .flat_map(|page| page.iter().copied())
+            .take(self.len)
+    }
+
+    pub fn to_vec(&self) -> Vec<i64> {
+        self.iter().collect()
+    }
+
+    /// How many pages are still shared with `other`, rather than copied.
+    pub fn shared_pages(&self, other: &Memory) -> usize {
+        self.pages
+            .iter()
+            .zip(other.pages.iter())
+            .filter(|(a, b)| Arc::ptr_eq(a, b))
+            .count()
+    }
+}
+
+impl Index<usize> for Memory {
+    type Output = i64;
+
+    fn index(&self, index: usize) -> &i64 {
+        assert!(index < self.len, "address {} out of bounds", index);
+        &self.pages[index / PAGE_SIZE][index % PAGE_SIZE]
+    }
+}
+
+impl PartialEq for Memory {
+    fn eq(&self, other: &Memory) -> bool {
+        self.len == other.len
+            && (Arc::ptr_eq(&self.pages, &other.pages) || self.iter().eq(other.iter()))
+    }
+}
+
+impl Eq for Memory {}
+
+impl Hash for Memory {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        for x in self.iter() {
+            x.hash(state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_on_write() {
+        let values: Vec<i64> = (0..PAGE_SIZE as i64 * 3 - 7).collect();
+        let original = Memory::from_slice(&values);
+        let mut copy = original.clone();
+        assert_eq!(copy.shared_pages(&original), 3);
+        copy.set(PAGE_SIZE + 1, -1).unwrap();
+        assert_eq!(copy.shared_pages(&original), 2);
+        assert_eq!(copy.set(values.len(), 0), None);
+
+        assert_eq!(original.to_vec(), values);
+        assert_eq!(copy[PAGE_SIZE + 1], -1);
+        assert_eq!(copy.get(PAGE_SIZE + 2), Some(PAGE_SIZE as i64 + 2));
+        assert_eq!(copy.get(values.len()), None);
+        assert_ne!(copy, original);
+        copy.set(PAGE_SIZE + 1, PAGE_SIZE as i64 + 1).unwrap();
+        assert_eq!(copy, original);
+
+        copy.grow(PAGE_SIZE * 5);
+        assert_eq!(copy.len(), PAGE_SIZE * 5);
+        assert_eq!(copy.get(values.len()), Some(0));
+        assert_eq!(copy.get(PAGE_SIZE * 5 - 1), Some(0));
+        assert_eq!(copy.shared_pages(&original), 2);
+        copy.set(PAGE_SIZE * 4, 7).unwrap();
+        assert_eq!(copy.get(PAGE_SIZE * 3), Some(0));
+    }
+}
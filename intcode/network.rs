@@ -0,0 +1,203 @@
+use crate::scheduler::{Policy, Scheduler};
+use crate::{Intcode, Result};
+
+pub type NodeId = usize;
+
+struct Node {
+    name: String,
+    prog: Intcode,
+    inputs: Vec<i64>,
+    links: Vec<NodeId>,
+    collect: bool,
+}
+
+/// A set of machines whose outputs feed each other's inputs.
+///
+/// Each machine first reads its initial inputs, then everything its incoming
+/// links send it, in the order they're sent. Every output is sent down all of
+/// the machine's outgoing links; outputs sent to a machine that has already
+/// halted are dropped. Machines are multiplexed on the calling thread by a
+/// `Scheduler`, so runs are reproducible.
+#[derive(Default)]
+pub struct Network {
+    nodes: Vec<Node>,
+}
+
+/// Outputs of the machines that were marked with `Network::collect`.
+#[derive(Debug)]
+pub struct NetworkOutput {
+    outputs: Vec<Vec<i64>>,
+}
+
+impl NetworkOutput {
+    pub fn outputs(&self, node: NodeId) -> &[i64] {
+        &self.outputs[node]
+    }
+
+    pub fn last_output(&self, node: NodeId) -> Option<i64> {
+        self.outputs[node].last().copied()
+    }
+}
+
+impl Network {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a machine that reads `initial_inputs`, such as a phase setting, before
+    /// any linked input.
+    pub fn add_machine<S: Into<String>>(
+        &mut self,
+        name: S,
+        prog: Intcode,
+        initial_inputs: &[i64],
+    ) -> NodeId {
+        self.nodes.push(Node {
+            name: name.into(),
+            prog,
+            inputs: Vec::from(initial_inputs),
+            links: Vec::new(),
+            collect: false,
+        });
+        self.nodes.len() - 1
+    }
+
+    /// Adds to the inputs a machine reads before any linked input.
+    pub fn push_input(&mut self, node: NodeId, value: i64) {
+        self.nodes[node].inputs.push(value);
+    }
+
+    /// Sends every output of `from` to `to`.
+    pub fn link(&mut self, from: NodeId, to: NodeId) {
+        self.nodes[from].links.push(to);
+    }
+
+    /// Links each machine to the next.
+    pub fn pipeline(&mut self, nodes: &[NodeId]) {
+        for pair in nodes.windows(2) {
+            self.link(pair[0], pair[1]);
+        }
+    }
+
+    /// Links each machine to the next, and the last back to the first.
+    pub fn ring(&mut self, nodes: &[NodeId]) {
+        self.pipeline(nodes);
+        if let (Some(&first), Some(&last)) = (nodes.first(), nodes.last()) {
+            self.link(last, first);
+        }
+    }
+
+    /// Keeps the outputs of `node` for the `NetworkOutput`.
+    pub fn collect(&mut self, node: NodeId) {
+        self.nodes[node].collect = true;
+    }
+
+    /// Runs every machine until they've all halted, running each until it blocks
+    /// in turn. Fails with `Error::Deadlock` if the machines left running are all
+    /// waiting for input that will never come.
+    pub fn run(self) -> Result<NetworkOutput> {
+        self.run_with(Policy::RunUntilBlocked)
+    }
+
+    /// Runs the network on one thread with the given scheduling policy.
+    pub fn run_with(self, policy: Policy) -> Result<NetworkOutput> {
+        let mut scheduler = Scheduler::new(policy);
+        let mut links = Vec::new();
+        let mut collect = Vec::new();
+        for node in self.nodes {
+            let id = scheduler.add(node.name, node.prog);
+            for value in node.inputs {
+                scheduler.push_input(id, value);
+            }
+            links.push(node.links);
+            collect.push(node.collect);
+        }
+        let mut outputs = vec![Vec::new(); links.len()];
+        while let Some(event) = scheduler.step()? {
+            if let Some(out) = event.output {
+                for &to in links[event.machine].iter() {
+                    scheduler.push_input(to, out);
+                }
+                if collect[event.machine] {
+                    outputs[event.machine].push(out);
+                }
+            }
+        }
+        scheduler.check_deadlock()?;
+        Ok(NetworkOutput { outputs })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::{MachineState, MachineStatus};
+    use crate::Error;
+
+    #[test]
+    fn fan_out_and_in() {
+        // Reads a value and outputs it plus one.
+        let add_one = Intcode::new(vec![3, 9, 1001, 9, 1, 9, 4, 9, 99, 0]);
+        // Reads two values and outputs their sum.
+        let sum = Intcode::new(vec![3, 11, 3, 12, 1, 11, 12, 11, 4, 11, 99, 0, 0]);
+        let mut network = Network::new();
+        let a = network.add_machine("a", add_one.clone(), &[5]);
+        let b = network.add_machine("b", add_one.clone(), &[]);
+        let c = network.add_machine("c", add_one, &[]);
+        let d = network.add_machine("d", sum, &[]);
+        network.link(a, b);
+        network.link(a, c);
+        network.link(b, d);
+        network.link(c, d);
+        network.collect(b);
+        network.collect(d);
+        let output = network.run_with(Policy::RoundRobin { quantum: 1 }).unwrap();
+        assert_eq!(output.outputs(b), &[7]);
+        assert_eq!(output.outputs(c), &[] as &[i64]);
+        assert_eq!(output.last_output(d), Some(14));
+    }
+
+    #[test]
+    fn deadlock() {
+        let add_one = Intcode::new(vec![3, 9, 1001, 9, 1, 9, 4, 9, 99, 0]);
+        let mut network = Network::new();
+        let a = network.add_machine("a", add_one.clone(), &[]);
+        let b = network.add_machine("b", add_one, &[]);
+        network.ring(&[a, b]);
+        let waiting = |name: &str| MachineStatus {
+            name: name.to_string(),
+            pc: 0,
+            state: MachineState::WaitingForInput,
+            inbox: vec![],
+        };
+        match network.run() {
+            Err(Error::Deadlock(machines)) => {
+                assert_eq!(machines, vec![waiting("a"), waiting("b")])
+            }
+            result => panic!("expected deadlock, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn machine_error() {
+        let mut network = Network::new();
+        let a = network.add_machine("a", Intcode::new(vec![104, 1, 99]), &[]);
+        let b = network.add_machine("b", Intcode::new(vec![3, 0, 42]), &[]);
+        network.link(a, b);
+        let crash = match network.run() {
+            Err(Error::Machine(crash)) => crash,
+            result => panic!("expected machine error, got {:?}", result),
+        };
+        assert_eq!((crash.name.as_str(), crash.index, crash.pc), ("b", 1, 2));
+        assert!(matches!(
+            crash.error,
+            Error::UnknownOpcode { pc: 2, opcode: 42 }
+        ));
+        assert_eq!(crash.last_inputs, vec![1]);
+        assert_eq!(crash.last_outputs, vec![] as Vec<i64>);
+        assert_eq!(
+            crash.diff.to_string(),
+            "1 cells changed in 1 ranges\n  0: 3 -> 1\n"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+use std::num;
+
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    /// Nothing between two commas.
+    Empty,
+    /// Two values separated only by whitespace.
+    MissingComma,
+    InvalidInt(num::ParseIntError),
+}
+
+/// A program element that couldn't be parsed, and where it is.
+#[derive(Debug)]
+pub struct ParseError {
+    /// The index of the element within the program.
+    pub index: usize,
+    /// 1-based line of the start of the element.
+    pub line: usize,
+    /// 1-based column of the start of the element.
+    pub column: usize,
+    pub text: String,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "element {} at line {}, column {}: ",
+            self.index, self.line, self.column
+        )?;
+        match &self.kind {
+            ParseErrorKind::Empty => write!(f, "empty element"),
+            ParseErrorKind::MissingComma => write!(f, "missing comma in {:?}", self.text),
+            ParseErrorKind::InvalidInt(e) => write!(f, "{:?} is not an integer ({})", self.text, e),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Element {
+    text: String,
+    line: usize,
+    column: usize,
+    /// Whitespace followed the text, so any more text is missing a comma.
+    ended: bool,
+}
+
+impl Element {
+    fn finish(self, index: usize) -> Result<i64, ParseError> {
+        let kind = if self.text.is_empty() {
+            ParseErrorKind::Empty
+        } else {
+            match self.text.parse() {
+                Ok(x) => return Ok(x),
+                Err(e) => ParseErrorKind::InvalidInt(e),
+            }
+        };
+        Err(ParseError {
+            index,
+            line: self.line,
+            column: self.column,
+            text: self.text,
+            kind,
+        })
+    }
+}
+
+/// Parses a comma separated program.
+///
+/// Whitespace is allowed anywhere between values, `#` starts a comment running
+/// to the end of the line, and a single trailing comma is ignored.
+pub fn parse_program(text: &str) -> Result<Vec<i64>, ParseError> {
+    let mut prog = Vec::new();
+    let mut elem = Element::default();
+    for (line_index, line) in text.lines().enumerate() {
+        let mut in_comment = false;
+        for (column_index, c) in line.chars().enumerate() {
+            match c {
+                _ if in_comment => {}
+                '#' => in_comment = true,
+                ',' => {
+                    if elem.text.is_empty() {
+                        elem.line = line_index + 1;
+                        elem.column = column_index + 1;
+                    }
+                    prog.push(std::mem::take(&mut elem).finish(prog.len())?);
+                }
+                _ if c.is_whitespace() => elem.ended = !elem.text.is_empty(),
+                _ => {
+                    if elem.ended {
+                        elem.text.push(' ');
+                        elem.text.push(c);
+                        return Err(ParseError {
+                            index: prog.len(),
+                            line: elem.line,
+                            column: elem.column,
+                            text: elem.text,
+                            kind: ParseErrorKind::MissingComma,
+                        });
+                    }
+                    if elem.text.is_empty() {
+                        elem.line = line_index + 1;
+                        elem.column = column_index + 1;
+                    }
+                    elem.text.push(c);
+                }
+            }
+        }
+        elem.ended = !elem.text.is_empty();
+    }
+    if !elem.text.is_empty() {
+        prog.push(elem.finish(prog.len())?);
+    }
+    Ok(prog)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tolerant() {
+        let text = "# header comment\n  1, 2,\n\t-3 ,4, # trailing comment\n99,\n";
+        assert_eq!(parse_program(text).unwrap(), vec![1, 2, -3, 4, 99]);
+        assert_eq!(parse_program("").unwrap(), vec![]);
+        assert_eq!(parse_program("1,0,0,0,99\n").unwrap(), vec![1, 0, 0, 0, 99]);
+    }
+
+    #[test]
+    fn error_locations() {
+        let err = parse_program("1,2,\n3,x4,5").unwrap_err();
+        assert_eq!((err.index, err.line, err.column), (3, 2, 3));
+        assert!(matches!(err.kind, ParseErrorKind::InvalidInt(_)));
+
+        let err = parse_program("1,\n  ,2").unwrap_err();
+        assert_eq!((err.index, err.line, err.column), (1, 2, 3));
+        assert!(matches!(err.kind, ParseErrorKind::Empty));
+
+        let err = parse_program("1,2\n3,4").unwrap_err();
+        assert_eq!((err.index, err.line, err.column), (1, 1, 3));
+        assert!(matches!(err.kind, ParseErrorKind::MissingComma));
+    }
+}
@@ -0,0 +1,69 @@
+use crate::memory::Memory;
+use crate::{parse, Intcode, Result};
+use std::io;
+
+/// An immutable program image, shared by every machine that runs it.
+///
+/// Cloning a `Program` or creating a machine from it only bumps reference
+/// counts; machines copy memory a page at a time as they write to it.
+#[derive(Clone, Debug)]
+pub struct Program {
+    /// The image, as the memory a machine starts with.
+    memory: Memory,
+}
+
+impl Program {
+    pub fn new(image: Vec<i64>) -> Self {
+        Program {
+            memory: Memory::from_slice(&image),
+        }
+    }
+
+    pub fn read<R: io::Read>(mut input: R) -> Result<Self> {
+        let mut text = String::new();
+        input.read_to_string(&mut text)?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(Self::new(parse::parse_program(text)?))
+    }
+
+    /// A copy of the image.
+    pub fn image(&self) -> Vec<i64> {
+        self.memory.to_vec()
+    }
+
+    /// The memory a machine running this program starts with.
+    pub fn initial_memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// A new machine, ready to run from the start of the program.
+    pub fn machine(&self) -> Intcode {
+        Intcode::from_program(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn machines_share_pages() {
+        let program = Program::parse("1,0,0,0,99").unwrap();
+        let mut machines: Vec<Intcode> = (0..1000).map(|_| program.machine()).collect();
+        machines[0].run(|| 0, |_| {}).unwrap();
+        assert_eq!(machines[0].memory().to_vec(), vec![2, 0, 0, 0, 99]);
+        assert_eq!(
+            machines[0].memory().shared_pages(program.initial_memory()),
+            0
+        );
+        assert_eq!(machines[1].memory(), program.initial_memory());
+        assert_eq!(
+            machines[1].memory().shared_pages(program.initial_memory()),
+            1
+        );
+        assert_eq!(machines[1].program().image(), program.image());
+    }
+}
@@ -0,0 +1,265 @@
+use crate::crash::IoHistory;
+use crate::{Intcode, Result, StepResult};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Packet {
+    pub dest: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Flow {
+    Continue,
+    Stop,
+}
+
+/// Watches a `Router` from outside, like the NAT of 2019 day 23.
+pub trait Monitor {
+    /// Receives a packet sent to an address with no NIC.
+    fn receive(&mut self, packet: Packet) -> Flow;
+
+    /// Called whenever every running NIC is idle. Packets pushed onto `inject`
+    /// are delivered; if there are none, nothing can happen and the router stops.
+    fn idle(&mut self, inject: &mut Vec<Packet>) -> Flow;
+}
+
+/// Remembers the last packet sent to it, and resends it to address 0 whenever
+/// the network goes idle. Stops once it sends the same Y value twice in a row.
+#[derive(Debug, Default)]
+pub struct Nat {
+    pub received: Vec<Packet>,
+    pub sent: Vec<Packet>,
+}
+
+impl Nat {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The Y value sent to address 0 twice in a row, once the NAT has stopped.
+    pub fn repeated_y(&self) -> Option<i64> {
+        match self.sent.as_slice() {
+            [.., a, b] if a.y == b.y => Some(b.y),
+            _ => None,
+        }
+    }
+}
+
+impl Monitor for Nat {
+    fn receive(&mut self, packet: Packet) -> Flow {
+        self.received.push(packet);
+        Flow::Continue
+    }
+
+    fn idle(&mut self, inject: &mut Vec<Packet>) -> Flow {
+        if let Some(&last) = self.received.last() {
+            let packet = Packet { dest: 0, ..last };
+            inject.push(packet);
+            self.sent.push(packet);
+        }
+        if self.repeated_y().is_some() {
+            Flow::Stop
+        } else {
+            Flow::Continue
+        }
+    }
+}
+
+struct Nic {
+    prog: Intcode,
+    queue: VecDeque<i64>,
+    /// Output values of a packet still being sent.
+    pending: Vec<i64>,
+    /// Reads from an empty queue since the NIC last sent or received anything.
+    idle_reads: usize,
+    halted: bool,
+    history: IoHistory,
+}
+
+impl Nic {
+    fn is_idle(&self, idle_reads: usize) -> bool {
+        self.halted
+            || (self.idle_reads >= idle_reads && self.queue.is_empty() && self.pending.is_empty())
+    }
+}
+
+/// Runs NICs that send each other `(destination, x, y)` packets.
+///
+/// Each NIC first reads its own address, then reads packets from its queue as an
+/// `x` and `y` pair, reading -1 when the queue is empty. Every NIC executes one
+/// instruction per round, so runs are deterministic.
+pub struct Router<M: Monitor> {
+    nics: Vec<Nic>,
+    monitor: M,
+    idle_reads: usize,
+}
+
+impl<M: Monitor> Router<M> {
+    /// Creates `count` NICs running `prog`, with addresses `0..count`.
+    pub fn new(prog: &Intcode, count: usize, monitor: M) -> Self {
+        Router {
+            nics: (0..count)
+                .map(|address| {
+                    let mut prog = prog.clone();
+                    prog.reset();
+                    Nic {
+                        prog,
+                        queue: VecDeque::from(vec![address as i64]),
+                        pending: Vec::new(),
+                        idle_reads: 0,
+                        halted: false,
+                        history: IoHistory::default(),
+                    }
+                })
+                .collect(),
+            monitor,
+            idle_reads: 2,
+        }
+    }
+
+    /// Sets how many times in a row a NIC must read from its empty queue to count
+    /// as idle. Defaults to 2.
+    pub fn set_idle_reads(&mut self, idle_reads: usize) {
+        self.idle_reads = idle_reads;
+    }
+
+    pub fn monitor(&self) -> &M {
+        &self.monitor
+    }
+
+    pub fn into_monitor(self) -> M {
+        self.monitor
+    }
+
+    fn route(&mut self, packet: Packet) -> Flow {
+        match usize::try_from(packet.dest)
+            .ok()
+            .and_then(|dest| self.nics.get_mut(dest))
+        {
+            Some(nic) => {
+                nic.queue.extend(&[packet.x, packet.y]);
+                nic.idle_reads = 0;
+                Flow::Continue
+            }
+            None => self.monitor.receive(packet),
+        }
+    }
+
+    /// Runs until the monitor stops the network, every NIC halts, or the network
+    /// is idle with nothing for the monitor to inject.
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            for i in 0..self.nics.len() {
+                if let Some(packet) = self.step_nic(i)? {
+                    if self.route(packet) == Flow::Stop {
+                        return Ok(());
+                    }
+                }
+            }
+            if self.nics.iter().all(|nic| nic.halted) {
+                return Ok(());
+            }
+            let idle_reads = self.idle_reads;
+            if self.nics.iter().all(|nic| nic.is_idle(idle_reads)) {
+                let mut inject = Vec::new();
+                if self.monitor.idle(&mut inject) == Flow::Stop || inject.is_empty() {
+                    return Ok(());
+                }
+                for packet in inject {
+                    if self.route(packet) == Flow::Stop {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Executes one instruction of a NIC, returning the packet it finished sending.
+    /// Errors are wrapped in `Error::Machine`, naming the NIC by its address.
+    fn step_nic(&mut self, i: usize) -> Result<Option<Packet>> {
+        let Nic {
+            prog,
+            queue,
+            pending,
+            idle_reads,
+            halted,
+            history,
+        } = &mut self.nics[i];
+        if *halted {
+            return Ok(None);
+        }
+        let mut polled_empty = false;
+        let mut active = false;
+        let sent_before = pending.len();
+        let result = prog.step(
+            &mut || {
+                let x = match queue.pop_front() {
+                    Some(x) => {
+                        active = true;
+                        x
+                    }
+                    None => {
+                        polled_empty = true;
+                        -1
+                    }
+                };
+                history.input(x);
+                x
+            },
+            &mut |out| pending.push(out),
+        );
+        let result = result.map_err(|e| history.crash(&format!("NIC {}", i), i, prog, e))?;
+        if let Some(&out) = pending.get(sent_before) {
+            history.output(out);
+        }
+        *halted = result == StepResult::Complete;
+        if polled_empty {
+            *idle_reads += 1;
+        }
+        if active || !pending.is_empty() {
+            *idle_reads = 0;
+        }
+        if let [dest, x, y] = pending.as_slice() {
+            let packet = Packet {
+                dest: *dest,
+                x: *x,
+                y: *y,
+            };
+            pending.clear();
+            return Ok(Some(packet));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nat() {
+        // NIC 0 sends (1, 10, 20). NIC 1 sends each packet it gets to 255, adding 1 to y.
+        let mut prog = vec![
+            3, 100, 1005, 100, 16, 104, 1, 104, 10, 104, 20, 3, 101, 1105, 1, 11, 3, 101, 1008,
+            101, -1, 103, 1005, 103, 16, 3, 102, 1001, 102, 1, 102, 104, 255, 4, 101, 4, 102, 1105,
+            1, 11,
+        ];
+        prog.resize(104, 0);
+        let mut router = Router::new(&Intcode::new(prog), 2, Nat::new());
+        router.run().unwrap();
+        let nat = router.into_monitor();
+        assert_eq!(
+            nat.received,
+            vec![Packet {
+                dest: 255,
+                x: 10,
+                y: 21
+            }]
+        );
+        assert_eq!(nat.sent.len(), 2);
+        assert_eq!(nat.repeated_y(), Some(21));
+    }
+}
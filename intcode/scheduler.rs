@@ -0,0 +1,271 @@
+use crate::crash::IoHistory;
+use crate::{Error, Intcode, Result, StepResult};
+use std::collections::VecDeque;
+
+pub type MachineId = usize;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Policy {
+    /// Runs each machine for up to `quantum` instructions before moving on to the
+    /// next, or until it halts or waits on input.
+    RoundRobin { quantum: usize },
+    /// Runs each machine until it halts or waits on input.
+    RunUntilBlocked,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MachineState {
+    Ready,
+    WaitingForInput,
+    Halted,
+}
+
+/// A snapshot of one machine in a `Scheduler`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MachineStatus {
+    pub name: String,
+    pub pc: usize,
+    pub state: MachineState,
+    /// Inputs queued but not yet read.
+    pub inbox: Vec<i64>,
+}
+
+/// What happened in one `Scheduler::step`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct StepEvent {
+    pub machine: MachineId,
+    /// The pc of the instruction that was executed.
+    pub pc: usize,
+    pub result: StepResult,
+    pub output: Option<i64>,
+}
+
+struct Slot {
+    name: String,
+    prog: Intcode,
+    inbox: VecDeque<i64>,
+    state: MachineState,
+    history: IoHistory,
+}
+
+/// Runs many machines on one thread, one instruction per `step`.
+///
+/// Machines are always considered in the order they were added, so a run is the
+/// same every time, step for step.
+pub struct Scheduler {
+    slots: Vec<Slot>,
+    policy: Policy,
+    current: MachineId,
+    /// Instructions the current machine has executed since it was picked.
+    used: usize,
+}
+
+impl Scheduler {
+    pub fn new(policy: Policy) -> Self {
+        Scheduler {
+            slots: Vec::new(),
+            policy,
+            current: 0,
+            used: 0,
+        }
+    }
+
+    /// Adds a machine, which starts from the beginning of its program.
+    pub fn add<S: Into<String>>(&mut self, name: S, mut prog: Intcode) -> MachineId {
+        prog.reset();
+        self.slots.push(Slot {
+            name: name.into(),
+            prog,
+            inbox: VecDeque::new(),
+            state: MachineState::Ready,
+            history: IoHistory::default(),
+        });
+        self.slots.len() - 1
+    }
+
+    /// Queues an input for a machine. Inputs for halted machines are dropped.
+    pub fn push_input(&mut self, id: MachineId, value: i64) {
+        let slot = &mut self.slots[id];
+        if slot.state == MachineState::Halted {
+            return;
+        }
+        slot.inbox.push_back(value);
+        slot.state = MachineState::Ready;
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn name(&self, id: MachineId) -> &str {
+        &self.slots[id].name
+    }
+
+    pub fn machine(&self, id: MachineId) -> &Intcode {
+        &self.slots[id].prog
+    }
+
+    pub fn state(&self, id: MachineId) -> MachineState {
+        self.slots[id].state
+    }
+
+    pub fn inbox(&self, id: MachineId) -> &VecDeque<i64> {
+        &self.slots[id].inbox
+    }
+
+    pub fn status(&self, id: MachineId) -> MachineStatus {
+        let slot = &self.slots[id];
+        MachineStatus {
+            name: slot.name.clone(),
+            pc: slot.prog.pc(),
+            state: slot.state,
+            inbox: slot.inbox.iter().copied().collect(),
+        }
+    }
+
+    /// Fails with `Error::Deadlock` if nothing can run, but not every machine has
+    /// halted. Only meaningful once `step` has returned `None` and there's no
+    /// outside input left to push.
+    pub fn check_deadlock(&self) -> Result<()> {
+        let stuck = self
+            .slots
+            .iter()
+            .any(|slot| slot.state == MachineState::WaitingForInput);
+        let runnable = self
+            .slots
+            .iter()
+            .any(|slot| slot.state == MachineState::Ready);
+        if stuck && !runnable {
+            Err(Error::Deadlock(
+                (0..self.slots.len()).map(|id| self.status(id)).collect(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn quantum(&self) -> usize {
+        match self.policy {
+            Policy::RoundRobin { quantum } => quantum,
+            Policy::RunUntilBlocked => usize::MAX,
+        }
+    }
+
+    fn advance(&mut self) {
+        self.current = (self.current + 1) % self.slots.len();
+        self.used = 0;
+    }
+
+    /// Executes one instruction of the machine the policy picks next, or returns
+    /// `None` if every machine has halted or is waiting for input. Errors are
+    /// wrapped in `Error::Machine` to say which machine failed.
+    pub fn step(&mut self) -> Result<Option<StepEvent>> {
+        for _ in 0..self.slots.len() {
+            if self.slots[self.current].state == MachineState::Ready {
+                break;
+            }
+            self.advance();
+        }
+        let machine = self.current;
+        let Slot {
+            name,
+            prog,
+            inbox,
+            state,
+            history,
+        } = match self.slots.get_mut(machine) {
+            Some(slot) if slot.state == MachineState::Ready => slot,
+            _ => return Ok(None),
+        };
+        let pc = prog.pc();
+        let mut output = None;
+        let result = prog.try_step(
+            &mut || {
+                let input = inbox.pop_front();
+                if let Some(x) = input {
+                    history.input(x);
+                }
+                input
+            },
+            &mut |out| output = Some(out),
+        );
+        let result = result.map_err(|e| history.crash(name, machine, prog, e))?;
+        if let Some(out) = output {
+            history.output(out);
+        }
+        *state = match result {
+            StepResult::Complete => MachineState::Halted,
+            StepResult::WaitingForInput => MachineState::WaitingForInput,
+            StepResult::Continue | StepResult::Paused(_) => MachineState::Ready,
+        };
+        let blocked = *state != MachineState::Ready;
+        self.used += 1;
+        if blocked || self.used >= self.quantum() {
+            self.advance();
+        }
+        Ok(Some(StepEvent {
+            machine,
+            pc,
+            result,
+            output,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_robin_order() {
+        // Outputs its input, then 1, 2 and 3.
+        let prog = Intcode::new(vec![3, 11, 4, 11, 104, 1, 104, 2, 104, 3, 99, 0]);
+        let trace = |policy| {
+            let mut scheduler = Scheduler::new(policy);
+            let a = scheduler.add("a", prog.clone());
+            let b = scheduler.add("b", prog.clone());
+            let mut outputs = Vec::new();
+            while let Some(event) = scheduler.step().unwrap() {
+                if let Some(out) = event.output {
+                    outputs.push((event.machine, out));
+                }
+                if event.result == StepResult::WaitingForInput {
+                    scheduler.push_input(event.machine, 10 * (event.machine as i64 + 1));
+                }
+            }
+            assert_eq!(scheduler.state(a), MachineState::Halted);
+            assert_eq!(scheduler.state(b), MachineState::Halted);
+            outputs
+        };
+        assert_eq!(
+            trace(Policy::RoundRobin { quantum: 2 }),
+            vec![
+                (0, 10),
+                (1, 20),
+                (0, 1),
+                (0, 2),
+                (1, 1),
+                (1, 2),
+                (0, 3),
+                (1, 3),
+            ]
+        );
+        assert_eq!(
+            trace(Policy::RunUntilBlocked),
+            vec![
+                (0, 10),
+                (0, 1),
+                (0, 2),
+                (0, 3),
+                (1, 20),
+                (1, 1),
+                (1, 2),
+                (1, 3),
+            ]
+        );
+    }
+}
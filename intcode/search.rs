@@ -0,0 +1,226 @@
+use crate::{Error, Result};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Permutations each worker claims at a time.
+const CHUNK: usize = 64;
+
+pub fn default_workers() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// A set of sequences to search, numbered in lexicographic order.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Sequences {
+    /// Orderings of `len` different elements of `values`.
+    Permutations { values: Vec<i64>, len: usize },
+    /// Every sequence of `len` elements of `values`, allowing repeats.
+    Product { values: Vec<i64>, len: usize },
+}
+
+impl Sequences {
+    /// Repeated values are only used once.
+    pub fn permutations(values: &[i64], len: usize) -> Self {
+        let mut values = Vec::from(values);
+        values.sort_unstable();
+        values.dedup();
+        Sequences::Permutations { values, len }
+    }
+
+    pub fn product(values: &[i64], len: usize) -> Self {
+        let mut values = Vec::from(values);
+        values.sort_unstable();
+        values.dedup();
+        Sequences::Product { values, len }
+    }
+
+    /// How many sequences there are, or `None` if there are too many to number.
+    pub fn count(&self) -> Option<usize> {
+        match self {
+            Sequences::Permutations { values, len } if *len <= values.len() => {
+                (values.len() - len + 1..=values.len()).try_fold(1usize, usize::checked_mul)
+            }
+            Sequences::Permutations { .. } => Some(0),
+            Sequences::Product { values, len } => {
+                (0..*len).try_fold(1usize, |count, _| count.checked_mul(values.len()))
+            }
+        }
+    }
+
+    /// The `index`th sequence, which must be less than `count`.
+    pub fn nth(&self, mut index: usize) -> Vec<i64> {
+        match self {
+            Sequences::Permutations { values, len } => {
+                let mut remaining = values.clone();
+                let mut sequence = Vec::with_capacity(*len);
+                for i in 0..*len {
+                    // Orderings of the rest, once this element is chosen.
+                    let count: usize = (remaining.len() - (len - i) + 1..remaining.len()).product();
+                    sequence.push(remaining.remove(index / count));
+                    index %= count;
+                }
+                sequence
+            }
+            Sequences::Product { values, len } => {
+                let mut sequence = vec![0; *len];
+                for x in sequence.iter_mut().rev() {
+                    *x = values[index % values.len()];
+                    index /= values.len();
+                }
+                sequence
+            }
+        }
+    }
+}
+
+/// Evaluates `func` on every sequence with a pool of `workers` threads, passing
+/// each sequence and its result to `visit`.
+///
+/// Workers claim sequences in chunks until they run out, so results are visited
+/// in no particular order. The first error stops every worker as soon as it
+/// finishes its current evaluation. Fails with `Error::TooManySequences` if the
+/// sequences can't be numbered.
+pub fn for_each_sequence<T, F, V>(
+    sequences: &Sequences,
+    workers: usize,
+    func: F,
+    visit: V,
+) -> Result<()>
+where
+    F: Fn(&[i64]) -> Result<T> + Sync,
+    V: Fn(&[i64], T) + Sync,
+{
+    let total = sequences.count().ok_or(Error::TooManySequences)?;
+    let next = AtomicUsize::new(0);
+    let cancelled = AtomicBool::new(false);
+    let error: Mutex<Option<Error>> = Mutex::new(None);
+    thread::scope(|scope| {
+        for _ in 0..workers.max(1) {
+            scope.spawn(|| loop {
+                let start = next.fetch_add(CHUNK, Ordering::Relaxed);
+                if start >= total {
+                    return;
+                }
+                for index in start..total.min(start + CHUNK) {
+                    if cancelled.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let sequence = sequences.nth(index);
+                    match func(&sequence) {
+                        Ok(x) => visit(&sequence, x),
+                        Err(e) => {
+                            cancelled.store(true, Ordering::Relaxed);
+                            error.lock().unwrap().get_or_insert(e);
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    });
+    match error.into_inner().unwrap() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::atomic::AtomicI64;
+
+    /// The largest result of `func` over every ordering of `values`.
+    fn max_over_permutations<F>(values: &[i64], workers: usize, func: F) -> Result<i64>
+    where
+        F: Fn(&[i64]) -> Result<i64> + Sync,
+    {
+        let best = AtomicI64::new(i64::MIN);
+        let sequences = Sequences::permutations(values, values.len());
+        for_each_sequence(&sequences, workers, func, |_, x| {
+            best.fetch_max(x, Ordering::Relaxed);
+        })?;
+        Ok(best.into_inner())
+    }
+
+    /// Steps to the next permutation in lexicographic order, returning false after the last.
+    fn next_permutation(values: &mut [i64]) -> bool {
+        let i = match (1..values.len()).rev().find(|&i| values[i - 1] < values[i]) {
+            Some(i) => i,
+            None => return false,
+        };
+        let j = (i..values.len())
+            .rev()
+            .find(|&j| values[i - 1] < values[j])
+            .unwrap();
+        values.swap(i - 1, j);
+        values[i..].reverse();
+        true
+    }
+
+    #[test]
+    fn permutation_order() {
+        let sequences = Sequences::permutations(&[1, 2, 3, 4], 4);
+        let mut permutation = sequences.nth(0);
+        let mut seen = HashSet::new();
+        for i in 0..sequences.count().unwrap() {
+            assert_eq!(permutation, sequences.nth(i));
+            seen.insert(permutation.clone());
+            assert_eq!(next_permutation(&mut permutation), i < 23);
+        }
+        assert_eq!(seen.len(), 24);
+    }
+
+    #[test]
+    fn sequence_order() {
+        let sequences = Sequences::permutations(&[3, 1, 2, 4], 4);
+        assert_eq!(sequences.count(), Some(24));
+        let all: Vec<Vec<i64>> = (0..24).map(|i| sequences.nth(i)).collect();
+        assert_eq!(all[0], vec![1, 2, 3, 4]);
+        assert_eq!(all[1], vec![1, 2, 4, 3]);
+        assert_eq!(all[23], vec![4, 3, 2, 1]);
+        assert!(all.windows(2).all(|w| w[0] < w[1]));
+
+        let sequences = Sequences::permutations(&[3, 1, 2, 4], 2);
+        assert_eq!(sequences.count(), Some(12));
+        assert_eq!(sequences.nth(0), vec![1, 2]);
+        assert_eq!(sequences.nth(3), vec![2, 1]);
+        assert_eq!(sequences.nth(11), vec![4, 3]);
+        assert_eq!(Sequences::permutations(&[1, 2], 3).count(), Some(0));
+
+        let sequences = Sequences::permutations(&[5, 6, 5], 2);
+        assert_eq!(sequences.count(), Some(2));
+        assert_eq!(sequences.nth(0), vec![5, 6]);
+        assert_eq!(sequences.nth(1), vec![6, 5]);
+        assert_eq!(Sequences::permutations(&[5, 5, 6], 3).count(), Some(0));
+
+        let sequences = Sequences::product(&[1, 0, 1], 3);
+        assert_eq!(sequences.count(), Some(8));
+        assert_eq!(sequences.nth(6), vec![1, 1, 0]);
+        assert_eq!(Sequences::product(&[0, 1, 2, 3, 4], 30).count(), None);
+        let result = for_each_sequence(
+            &Sequences::product(&[0, 1, 2, 3, 4], 30),
+            1,
+            |_| Ok(()),
+            |_, ()| {},
+        );
+        assert!(matches!(result, Err(Error::TooManySequences)));
+    }
+
+    #[test]
+    fn max_and_cancel() {
+        let weighted = |p: &[i64]| -> i64 { p.iter().enumerate().map(|(i, x)| i as i64 * x).sum() };
+        let best = max_over_permutations(&[4, 2, 3, 1, 5, 6, 7], 4, |p| Ok(weighted(p)));
+        assert_eq!(best.unwrap(), 112);
+
+        let result = max_over_permutations(&[4, 2, 3, 1, 5, 6, 7], 4, |p| {
+            if p == [7, 6, 5, 4, 3, 2, 1] {
+                Err(Error::IndexOutOfBounds(7))
+            } else {
+                Ok(weighted(p))
+            }
+        });
+        assert!(matches!(result, Err(Error::IndexOutOfBounds(7))));
+    }
+}
@@ -0,0 +1,209 @@
+use std::ops::{Bound, Range, RangeBounds};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Which accesses a watchpoint fires on.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(self, access: Access) -> bool {
+        match self {
+            WatchKind::Read => access == Access::Read,
+            WatchKind::Write => access == Access::Write,
+            WatchKind::ReadWrite => true,
+        }
+    }
+}
+
+/// A data access to a watched address.
+/// Instruction fetches and immediate parameters are not data accesses.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WatchEvent {
+    /// The instruction making the access.
+    pub pc: usize,
+    pub address: usize,
+    pub access: Access,
+    pub old: i64,
+    /// The value written, or the same as `old` for a read.
+    pub new: i64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WatchAction {
+    Continue,
+    /// Stop once the current instruction finishes.
+    Pause,
+}
+
+pub type WatchId = usize;
+
+type Callback = Arc<Mutex<dyn FnMut(&WatchEvent) -> WatchAction + Send>>;
+
+/// Clones of a program share the callbacks of the watchpoints they were cloned with.
+#[derive(Clone)]
+struct Watchpoint {
+    id: WatchId,
+    range: Range<usize>,
+    kind: WatchKind,
+    callback: Callback,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Watchpoints {
+    points: Vec<Watchpoint>,
+    next_id: WatchId,
+    paused: Option<WatchEvent>,
+}
+
+impl Watchpoints {
+    pub fn add<R, F>(&mut self, addresses: R, kind: WatchKind, callback: F) -> WatchId
+    where
+        R: RangeBounds<usize>,
+        F: FnMut(&WatchEvent) -> WatchAction + Send + 'static,
+    {
+        let start = match addresses.start_bound() {
+            Bound::Included(&x) => x,
+            Bound::Excluded(&x) => x.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match addresses.end_bound() {
+            Bound::Included(&x) => x.saturating_add(1),
+            Bound::Excluded(&x) => x,
+            Bound::Unbounded => usize::MAX,
+        };
+        let id = self.next_id;
+        self.next_id += 1;
+        self.points.push(Watchpoint {
+            id,
+            range: start..end,
+            kind,
+            callback: Arc::new(Mutex::new(callback)),
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: WatchId) -> bool {
+        let len = self.points.len();
+        self.points.retain(|w| w.id != id);
+        self.points.len() != len
+    }
+
+    pub fn fire(&mut self, event: WatchEvent) {
+        for w in self.points.iter() {
+            if !w.range.contains(&event.address) || !w.kind.matches(event.access) {
+                continue;
+            }
+            let action = (*w.callback.lock().unwrap())(&event);
+            if action == WatchAction::Pause && self.paused.is_none() {
+                self.paused = Some(event);
+            }
+        }
+    }
+
+    /// The first event to ask for a pause since the last call.
+    pub fn take_pause(&mut self) -> Option<WatchEvent> {
+        self.paused.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Intcode, StepResult};
+
+    fn no_input() -> i64 {
+        panic!("no input")
+    }
+
+    #[test]
+    fn writes() {
+        let prog = &mut Intcode::new(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = events.clone();
+        prog.watch(0..4, WatchKind::Write, move |event| {
+            log.lock().unwrap().push(*event);
+            WatchAction::Continue
+        });
+        assert_eq!(prog.run(no_input, |_| {}).unwrap(), StepResult::Complete);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                WatchEvent {
+                    pc: 0,
+                    address: 3,
+                    access: Access::Write,
+                    old: 3,
+                    new: 70,
+                },
+                WatchEvent {
+                    pc: 4,
+                    address: 0,
+                    access: Access::Write,
+                    old: 1,
+                    new: 3500,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let prog = &mut Intcode::new(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        let id = prog.watch(10..=10, WatchKind::Read, |_| WatchAction::Pause);
+        let paused = prog.run(no_input, |_| {}).unwrap();
+        assert_eq!(
+            paused,
+            StepResult::Paused(WatchEvent {
+                pc: 0,
+                address: 10,
+                access: Access::Read,
+                old: 40,
+                new: 40,
+            })
+        );
+        assert_eq!(prog.pc(), 4);
+        assert!(prog.unwatch(id));
+        assert_eq!(prog.resume(no_input, |_| {}).unwrap(), StepResult::Complete);
+        assert_eq!(prog.memory()[0], 3500);
+    }
+
+    #[test]
+    fn failed_instruction_does_not_pause_later() {
+        // Reads 9, then jumps to -1. The add at 3 runs fine.
+        let prog = &mut Intcode::new(vec![1005, 9, -1, 1101, 0, 0, 10, 99, 0, 1, 0]);
+        prog.watch(9..=9, WatchKind::Read, |_| WatchAction::Pause);
+        let mut pc = 0;
+        assert!(prog
+            .run_instruction(&mut pc, &mut no_input, &mut |_| {})
+            .is_err());
+        pc = 3;
+        assert_eq!(
+            prog.run_instruction(&mut pc, &mut no_input, &mut |_| {})
+                .unwrap(),
+            StepResult::Continue
+        );
+    }
+
+    #[test]
+    fn ranges_at_the_top_of_memory() {
+        let mut watches = Watchpoints::default();
+        watches.add(..=usize::MAX, WatchKind::Write, |_| WatchAction::Continue);
+        watches.add(
+            (Bound::Excluded(usize::MAX), Bound::Unbounded),
+            WatchKind::Write,
+            |_| WatchAction::Continue,
+        );
+        let ranges: Vec<_> = watches.points.iter().map(|w| w.range.clone()).collect();
+        assert_eq!(ranges, vec![0..usize::MAX, usize::MAX..usize::MAX]);
+    }
+}
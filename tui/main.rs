@@ -0,0 +1,171 @@
+mod session;
+mod view;
+
+use intcode::isa::Profile;
+use intcode::Intcode;
+use session::Session;
+use std::env;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::process::{self, Command, Stdio};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: intcode-tui <program> [--input <values>]... [--speed <n>] \
+                     [--profile <day2|day5|full>]";
+
+/// How often the screen is redrawn.
+const FRAME: Duration = Duration::from_millis(33);
+/// Most instructions run between frames, so fast speeds stay responsive.
+const MAX_FRAME_STEPS: usize = 1_000_000;
+
+fn fail(msg: impl Display) -> ! {
+    eprintln!("intcode-tui: {}", msg);
+    process::exit(1)
+}
+
+/// Runs `stty` on the controlling terminal.
+fn stty(args: &[&str]) -> Option<String> {
+    let tty = File::open("/dev/tty").ok()?;
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(tty)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The terminal's width and height.
+fn terminal_size() -> (usize, usize) {
+    let size = stty(&["size"]).unwrap_or_default();
+    let mut numbers = size.split_whitespace().filter_map(|n| n.parse().ok());
+    match (numbers.next(), numbers.next()) {
+        (Some(rows), Some(columns)) => (columns, rows),
+        _ => (80, 24),
+    }
+}
+
+/// Puts the terminal in raw mode on the alternate screen until dropped.
+struct Terminal {
+    saved: String,
+}
+
+impl Terminal {
+    fn enter() -> Self {
+        let saved = stty(&["-g"]).unwrap_or_else(|| fail("stdin is not a terminal"));
+        stty(&["raw", "-echo"]);
+        print!("\x1b[?1049h\x1b[?25l");
+        Terminal { saved }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        io::stdout().flush().ok();
+        stty(&[&self.saved]);
+    }
+}
+
+fn parse_args() -> Session {
+    let mut args = env::args().skip(1);
+    let mut program = None;
+    let mut inputs = Vec::new();
+    let mut speed = 10.0;
+    let mut profile = None;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .unwrap_or_else(|| fail(format!("{} needs a value", name)))
+        };
+        match arg.as_str() {
+            "--input" => {
+                for v in value("--input").split(',') {
+                    let v = v.trim();
+                    inputs.push(
+                        v.parse()
+                            .unwrap_or_else(|_| fail(format!("bad input {:?}", v))),
+                    );
+                }
+            }
+            "--speed" => {
+                let v = value("--speed");
+                speed = v
+                    .parse()
+                    .ok()
+                    .filter(|&s: &f64| s >= 1.0)
+                    .unwrap_or_else(|| fail(format!("bad --speed {:?}", v)));
+            }
+            "--profile" => {
+                let v = value("--profile");
+                profile = Some(v.parse::<Profile>().unwrap_or_else(|e| fail(e)));
+            }
+            _ if arg.starts_with("--") => fail(format!("unknown option {}\n{}", arg, USAGE)),
+            _ if program.is_none() => program = Some(arg),
+            _ => fail(format!("unexpected argument {}\n{}", arg, USAGE)),
+        }
+    }
+    let path = program.unwrap_or_else(|| fail(USAGE));
+    let text =
+        fs::read_to_string(&path).unwrap_or_else(|e| fail(format!("cannot read {}: {}", path, e)));
+    let mut machine = Intcode::parse(&text).unwrap_or_else(|e| fail(format!("{}: {:?}", path, e)));
+    if let Some(profile) = profile {
+        machine.set_instruction_set(Arc::new(profile));
+    }
+    Session::new(machine, &inputs, speed)
+}
+
+fn main() {
+    let mut session = parse_args();
+    let terminal = Terminal::enter();
+    let (keys, pressed) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 64];
+        while let Ok(read @ 1..) = io::stdin().read(&mut buffer) {
+            for &byte in &buffer[..read] {
+                if keys.send(byte).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    let mut size = terminal_size();
+    let mut size_checked = Instant::now();
+    let mut last = Instant::now();
+    // Fractions of an instruction owed from earlier frames.
+    let mut budget = 0.0;
+    'frames: loop {
+        while let Ok(key) = pressed.try_recv() {
+            if !session.key(key) {
+                break 'frames;
+            }
+        }
+        let now = Instant::now();
+        if session.paused || !session.runnable() {
+            budget = 0.0;
+        } else {
+            budget += session.speed * now.duration_since(last).as_secs_f64();
+            let steps = (budget as usize).min(MAX_FRAME_STEPS);
+            // Don't build up a backlog when capped.
+            budget = (budget - steps as f64).min(1.0);
+            session.run(steps);
+        }
+        last = now;
+        if now.duration_since(size_checked) > Duration::from_secs(1) {
+            size = terminal_size();
+            size_checked = now;
+        }
+        print!("{}", view::render(&session, size.0, size.1));
+        io::stdout().flush().ok();
+        thread::sleep(FRAME);
+    }
+    drop(terminal);
+}
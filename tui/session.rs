@@ -0,0 +1,179 @@
+use intcode::{Intcode, StepResult, WatchAction, WatchKind};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// How many of the latest writes are highlighted.
+pub const RECENT_WRITES: usize = 16;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Status {
+    Ready,
+    WaitingForInput,
+    Halted,
+    Failed(String),
+}
+
+/// A machine being watched, with its queued inputs and everything it output.
+pub struct Session {
+    pub machine: Intcode,
+    pub inputs: VecDeque<i64>,
+    pub outputs: Vec<i64>,
+    /// Addresses written, newest last.
+    writes: Arc<Mutex<VecDeque<usize>>>,
+    initial_inputs: Vec<i64>,
+    pub steps: u64,
+    pub status: Status,
+    pub paused: bool,
+    /// Instructions per second while running.
+    pub speed: f64,
+    /// An input being typed.
+    pub typing: Option<String>,
+}
+
+impl Session {
+    pub fn new(mut machine: Intcode, inputs: &[i64], speed: f64) -> Self {
+        let writes = Arc::new(Mutex::new(VecDeque::new()));
+        let recorded = writes.clone();
+        machine.watch(.., WatchKind::Write, move |event| {
+            let mut writes = recorded.lock().unwrap();
+            if writes.len() == RECENT_WRITES {
+                writes.pop_front();
+            }
+            writes.push_back(event.address);
+            WatchAction::Continue
+        });
+        Session {
+            machine,
+            inputs: inputs.iter().copied().collect(),
+            outputs: Vec::new(),
+            writes,
+            initial_inputs: Vec::from(inputs),
+            steps: 0,
+            status: Status::Ready,
+            paused: true,
+            speed,
+            typing: None,
+        }
+    }
+
+    /// Addresses of the latest writes, newest first.
+    pub fn recent_writes(&self) -> Vec<usize> {
+        self.writes.lock().unwrap().iter().rev().copied().collect()
+    }
+
+    /// Whether the machine can take another step.
+    pub fn runnable(&self) -> bool {
+        match self.status {
+            Status::Ready => true,
+            Status::WaitingForInput => !self.inputs.is_empty(),
+            Status::Halted | Status::Failed(_) => false,
+        }
+    }
+
+    /// Runs up to `steps` instructions, stopping early if the machine halts,
+    /// fails or needs input.
+    pub fn run(&mut self, steps: usize) {
+        for _ in 0..steps {
+            if !self.runnable() {
+                return;
+            }
+            self.step();
+        }
+    }
+
+    fn step(&mut self) {
+        let Session {
+            machine,
+            inputs,
+            outputs,
+            ..
+        } = self;
+        let pc = machine.pc();
+        let result = machine.try_step(&mut || inputs.pop_front(), &mut |out| outputs.push(out));
+        self.status = match result {
+            Ok(StepResult::Continue) | Ok(StepResult::Paused(_)) => Status::Ready,
+            Ok(StepResult::WaitingForInput) => Status::WaitingForInput,
+            Ok(StepResult::Complete) => Status::Halted,
+            Err(e) => Status::Failed(format!("pc {}: {:?}", pc, e)),
+        };
+        if self.status != Status::WaitingForInput {
+            self.steps += 1;
+        }
+    }
+
+    /// Starts over with the initial inputs.
+    pub fn reset(&mut self) {
+        self.machine.reset();
+        self.inputs = self.initial_inputs.iter().copied().collect();
+        self.outputs.clear();
+        self.writes.lock().unwrap().clear();
+        self.steps = 0;
+        self.status = Status::Ready;
+    }
+
+    /// Handles a key, returning false to quit.
+    pub fn key(&mut self, key: u8) -> bool {
+        if let Some(text) = &mut self.typing {
+            match key {
+                b'0'..=b'9' | b'-' => text.push(key as char),
+                // Backspace.
+                8 | 127 => {
+                    text.pop();
+                }
+                b'\r' | b'\n' => {
+                    if let Ok(value) = text.parse() {
+                        self.inputs.push_back(value);
+                    }
+                    self.typing = None;
+                }
+                // Escape.
+                27 => self.typing = None,
+                _ => {}
+            }
+            return true;
+        }
+        match key {
+            b'q' => return false,
+            b' ' => self.paused = !self.paused,
+            b's' | b'.' => {
+                self.paused = true;
+                self.run(1);
+            }
+            b'+' | b'=' => self.speed = (self.speed * 2.0).min(1e8),
+            b'-' => self.speed = (self.speed / 2.0).max(1.0),
+            b'i' => self.typing = Some(String::new()),
+            b'r' => self.reset(),
+            _ => {}
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_and_input() {
+        // Reads a value into 9, adds 5 and outputs it.
+        let machine = Intcode::new(vec![3, 9, 1001, 9, 5, 9, 4, 9, 99, 0]);
+        let mut session = Session::new(machine, &[], 10.0);
+        session.run(5);
+        assert_eq!(session.status, Status::WaitingForInput);
+        assert_eq!(session.steps, 0);
+        for &key in b"i-3\r" {
+            session.key(key);
+        }
+        assert_eq!(session.inputs, vec![-3]);
+        session.key(b's');
+        session.run(1);
+        assert_eq!(session.recent_writes(), vec![9, 9]);
+        session.run(10);
+        assert_eq!(session.outputs, vec![2]);
+        assert_eq!(session.status, Status::Halted);
+        assert_eq!(session.steps, 4);
+        session.key(b'r');
+        assert_eq!(session.machine.memory()[9], 0);
+        assert!(session.recent_writes().is_empty());
+    }
+}
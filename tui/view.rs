@@ -0,0 +1,187 @@
+use crate::session::{Session, Status};
+use intcode::disasm;
+use std::fmt::Write;
+
+const RESET: &str = "\x1b[0m";
+const PC: &str = "\x1b[7m";
+const NEWEST_WRITE: &str = "\x1b[1;31m";
+const OLDER_WRITE: &str = "\x1b[33m";
+const DIM: &str = "\x1b[2m";
+/// Writes this recent are shown in the brighter color.
+const NEWEST: usize = 4;
+
+/// The width of a memory cell, including the space before it.
+const CELL: usize = 7;
+/// The width of the address at the start of each grid row.
+const LABEL: usize = 6;
+const LISTING: usize = 30;
+
+fn cell_style(session: &Session, writes: &[usize], address: usize) -> Option<&'static str> {
+    if address == session.machine.pc() {
+        return Some(PC);
+    }
+    let age = writes.iter().position(|&w| w == address)?;
+    Some(if age < NEWEST {
+        NEWEST_WRITE
+    } else {
+        OLDER_WRITE
+    })
+}
+
+/// Fits `text` in `width` columns.
+fn fit(text: &str, width: usize) -> String {
+    let text: String = text.chars().take(width).collect();
+    format!("{:width$}", text, width = width)
+}
+
+/// The rows of the memory grid, each `LABEL + columns * CELL` wide, scrolled
+/// to keep the pc in view.
+fn grid(session: &Session, columns: usize, rows: usize) -> Vec<String> {
+    let memory = session.machine.memory();
+    let writes = session.recent_writes();
+    let total = memory.len().div_ceil(columns);
+    let pc_row = session.machine.pc() / columns;
+    let first = pc_row
+        .saturating_sub(rows / 3)
+        .min(total.saturating_sub(rows));
+    (first..first + rows)
+        .map(|row| {
+            let mut line = String::new();
+            if row >= total {
+                return " ".repeat(LABEL + columns * CELL);
+            }
+            write!(line, "{:>5}:", row * columns).unwrap();
+            for address in row * columns..(row + 1) * columns {
+                let text = match memory.get(address) {
+                    Some(value) => fit(&format!("{:>6}", value), CELL - 1),
+                    None => " ".repeat(CELL - 1),
+                };
+                match cell_style(session, &writes, address) {
+                    Some(style) => write!(line, " {}{}{}", style, text, RESET).unwrap(),
+                    None => write!(line, " {}", text).unwrap(),
+                }
+            }
+            line
+        })
+        .collect()
+}
+
+/// The instructions from the pc on, `width` columns wide.
+fn listing(session: &Session, width: usize, rows: usize) -> Vec<String> {
+    let machine = &session.machine;
+    let pc = machine.pc();
+    let items = disasm::disassemble(
+        &**machine.instruction_set(),
+        machine.memory(),
+        pc..machine.memory().len(),
+    );
+    let mut lines: Vec<String> = items
+        .iter()
+        .take(rows)
+        .map(|item| {
+            let text = fit(&format!("{:>5}  {}", item.address(), item), width);
+            if item.address() == pc {
+                format!("{}{}{}", PC, text, RESET)
+            } else {
+                text
+            }
+        })
+        .collect();
+    lines.resize(rows, " ".repeat(width));
+    lines
+}
+
+fn join(values: &mut dyn Iterator<Item = &i64>) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+/// Draws a frame for a terminal `width` by `height`, from the top left corner.
+pub fn render(session: &Session, width: usize, height: usize) -> String {
+    let machine = &session.machine;
+    let state = if session.paused { "paused" } else { "running" };
+    let status = match &session.status {
+        Status::Ready => String::new(),
+        Status::WaitingForInput if session.inputs.is_empty() => {
+            "waiting for input (press i)".to_string()
+        }
+        Status::WaitingForInput => String::new(),
+        Status::Halted => "halted".to_string(),
+        Status::Failed(error) => format!("failed at {}", error),
+    };
+    let mut lines = vec![format!(
+        "pc {}  rb {}  steps {}  {} at {}/s  {}",
+        machine.pc(),
+        machine.relative_base(),
+        session.steps,
+        state,
+        session.speed,
+        status
+    )];
+
+    let rows = height.saturating_sub(4).max(1);
+    let columns = (width.saturating_sub(LISTING + 2 + LABEL) / CELL).max(1);
+    let grid = grid(session, columns, rows);
+    let listing = listing(session, LISTING, rows);
+    for (cells, instruction) in grid.iter().zip(listing.iter()) {
+        lines.push(format!("{}  {}", cells, instruction));
+    }
+
+    let inputs = match &session.typing {
+        Some(text) => format!("input> {}_", text),
+        None => format!("in: {}", join(&mut session.inputs.iter())),
+    };
+    lines.push(fit(&inputs, width));
+    // The latest outputs that fit.
+    let mut shown = 0;
+    let mut outputs = String::new();
+    for value in session.outputs.iter().rev() {
+        let text = format!("{}, ", value);
+        if shown + text.len() + 5 > width {
+            break;
+        }
+        outputs.insert_str(0, &text);
+        shown += text.len();
+    }
+    lines.push(format!("out: {}", outputs.trim_end_matches(", ")));
+    lines.push(format!(
+        "{}space run/pause  s step  +/- speed  i input  r reset  q quit{}",
+        DIM, RESET
+    ));
+
+    let mut frame = String::from("\x1b[H");
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            frame.push_str("\r\n");
+        }
+        frame.push_str(line);
+        frame.push_str("\x1b[K");
+    }
+    frame.push_str("\x1b[J");
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use intcode::Intcode;
+
+    #[test]
+    fn frame() {
+        let machine = Intcode::new(vec![3, 9, 1001, 9, 5, 9, 4, 9, 99, 0]);
+        let mut session = Session::new(machine, &[7], 10.0);
+        session.run(2);
+        let frame = render(&session, 100, 12);
+        let lines: Vec<&str> = frame.split("\r\n").collect();
+        assert_eq!(lines.len(), 12);
+        assert!(lines[0].contains("pc 6  rb 0  steps 2  paused"));
+        // The pc is highlighted in the grid and the listing.
+        assert!(lines[1].contains(&format!(" {}     4{}", PC, RESET)));
+        assert!(lines[1].contains(&format!("{}    6  out [9]", PC)));
+        // So is the write to 9, with its new value.
+        assert!(lines[2].contains(&format!(" {}    12{}", NEWEST_WRITE, RESET)));
+        assert!(lines[2].contains("    8  halt"));
+        assert!(lines[9].starts_with("in: "));
+        session.run(1);
+        assert!(render(&session, 100, 12).contains("out: 12\x1b[K"));
+    }
+}